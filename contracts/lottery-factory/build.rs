@@ -0,0 +1,48 @@
+//! Builds the lottery wasm the factory imports its types and client from and
+//! deploys in tests, so a clean checkout compiles without a prior
+//! `stellar contract build`.
+
+use std::{env, fs, path::PathBuf, process::Command};
+
+const LOTTERY_PACKAGE: &str = "no-loss-lottery";
+const LOTTERY_WASM: &str = "no_loss_lottery.wasm";
+const WASM_TARGET: &str = "wasm32v1-none";
+
+fn main() {
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let lottery_dir = manifest_dir.join("../no-loss-lottery");
+    println!(
+        "cargo:rerun-if-changed={}",
+        lottery_dir.join("src").display()
+    );
+    println!(
+        "cargo:rerun-if-changed={}",
+        lottery_dir.join("Cargo.toml").display()
+    );
+
+    // A separate target directory keeps the nested build off the lock of
+    // the one building the factory
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let target_dir = out_dir.join("lottery-target");
+    let status = Command::new(env::var("CARGO").unwrap_or_else(|_| "cargo".into()))
+        .current_dir(&manifest_dir)
+        .args(["build", "--release", "--package", LOTTERY_PACKAGE])
+        .args(["--target", WASM_TARGET])
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .env_remove("CARGO_ENCODED_RUSTFLAGS")
+        .env_remove("RUSTFLAGS")
+        .status()
+        .expect("failed to run cargo for the lottery wasm");
+    assert!(status.success(), "building the lottery wasm failed");
+
+    let wasm = target_dir
+        .join(WASM_TARGET)
+        .join("release")
+        .join(LOTTERY_WASM);
+    fs::write(
+        out_dir.join("lottery.rs"),
+        format!("soroban_sdk::contractimport!(file = {:?});\n", wasm),
+    )
+    .unwrap();
+}
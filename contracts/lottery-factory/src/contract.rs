@@ -0,0 +1,414 @@
+use soroban_sdk::{contract, contractimpl, Address, BytesN, Env, Vec};
+
+use crate::error::FactoryError;
+use crate::storage::LotteryRecord;
+use crate::{events, storage};
+
+mod blend {
+    soroban_sdk::contractimport!(file = "../wasm/pool.wasm");
+}
+
+// Built from the workspace by `build.rs`
+mod lottery {
+    include!(concat!(env!("OUT_DIR"), "/lottery.rs"));
+}
+use lottery::PhaseDurations;
+
+#[contract]
+struct LotteryFactory;
+
+#[contractimpl]
+impl LotteryFactory {
+    pub fn __constructor(e: Env, admin: Address, wasm_hash: BytesN<32>) {
+        storage::write_admin(&e, &admin);
+        storage::write_wasm_hash(&e, &wasm_hash);
+    }
+
+    /// Points new deployments at another uploaded lottery wasm. Lotteries
+    /// that are already deployed keep their code.
+    pub fn set_wasm_hash(e: Env, wasm_hash: BytesN<32>) -> Result<(), FactoryError> {
+        let admin = storage::read_admin(&e).ok_or(FactoryError::AdminNotFound)?;
+        admin.require_auth();
+
+        storage::write_wasm_hash(&e, &wasm_hash);
+        events::wasm_hash_updated(&e, &wasm_hash);
+        Ok(())
+    }
+
+    pub fn get_wasm_hash(e: Env) -> Result<BytesN<32>, FactoryError> {
+        storage::read_wasm_hash(&e)
+    }
+
+    pub fn get_admin(e: Env) -> Option<Address> {
+        storage::read_admin(&e)
+    }
+
+    /// Deploys a lottery with the given constructor parameters and adds it to
+    /// the registry. `admin` becomes the lottery's admin.
+    pub fn deploy_lottery(
+        e: Env,
+        admin: Address,
+        token: Address,
+        ticket_amount: i128,
+        blend_address: Address,
+        phase_durations: PhaseDurations,
+    ) -> Result<Address, FactoryError> {
+        let factory_admin = storage::read_admin(&e).ok_or(FactoryError::AdminNotFound)?;
+        factory_admin.require_auth();
+
+        if ticket_amount <= 0 {
+            return Err(FactoryError::InvalidAmount);
+        }
+
+        let blend_client = blend::Client::new(&e, &blend_address);
+        if !blend_client.get_reserve_list().contains(&token) {
+            return Err(FactoryError::TokenNotInBlendPool);
+        }
+
+        let wasm_hash = storage::read_wasm_hash(&e)?;
+        let id = storage::read_lottery_count(&e) + 1;
+        let address = e
+            .deployer()
+            .with_current_contract(Self::salt(&e, id))
+            .deploy_v2(
+                wasm_hash,
+                (
+                    admin.clone(),
+                    token.clone(),
+                    ticket_amount,
+                    blend_address.clone(),
+                    phase_durations,
+                ),
+            );
+
+        let record = LotteryRecord {
+            id,
+            address: address.clone(),
+            admin,
+            token,
+            ticket_amount,
+            blend_address,
+            deployed_ledger: e.ledger().sequence(),
+        };
+        storage::write_lottery(&e, &record);
+        storage::write_lottery_count(&e, id);
+        events::lottery_deployed(&e, &address, &record);
+        Ok(address)
+    }
+
+    pub fn get_lottery(e: Env, id: u32) -> Result<LotteryRecord, FactoryError> {
+        storage::read_lottery(&e, id)
+    }
+
+    pub fn get_lottery_count(e: Env) -> u32 {
+        storage::read_lottery_count(&e)
+    }
+
+    pub fn list_lotteries(e: Env, start_id: u32, limit: u32) -> Vec<LotteryRecord> {
+        let mut lotteries = Vec::new(&e);
+        let limit = limit.min(storage::MAX_LOTTERIES_PAGE_SIZE);
+        for id in start_id..start_id.saturating_add(limit) {
+            match storage::read_lottery(&e, id) {
+                Ok(record) => lotteries.push_back(record),
+                Err(_) => break, // Lotteries are registered in order
+            }
+        }
+        lotteries
+    }
+}
+
+impl LotteryFactory {
+    fn salt(e: &Env, id: u32) -> BytesN<32> {
+        let mut salt = [0; 32];
+        salt[28..].copy_from_slice(&id.to_be_bytes());
+        BytesN::from_array(e, &salt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use soroban_sdk::{
+        testutils::{Address as _, AuthorizedFunction, Events, Ledger},
+        vec, IntoVal, Symbol, Val,
+    };
+
+    use super::*;
+
+    mod deploy {
+        use super::*;
+
+        #[test]
+        fn deploy_lottery() {
+            let e = Env::default();
+            e.mock_all_auths();
+            e.ledger().set_sequence_number(100);
+            let test_env = setup_test_env(&e);
+
+            let address = deploy(&test_env);
+
+            let lottery_client = lottery::Client::new(&e, &address);
+            assert_eq!(
+                Some(test_env.lottery_admin.clone()),
+                lottery_client.get_admin()
+            );
+            assert_eq!(10_000_000, lottery_client.get_ticket_amount());
+            assert_eq!(
+                lottery::LotteryStatus::BuyIn,
+                lottery_client.get_lottery_state().status
+            );
+
+            let record = test_env.factory_client.get_lottery(&1);
+            assert_eq!(
+                LotteryRecord {
+                    id: 1,
+                    address: address.clone(),
+                    admin: test_env.lottery_admin,
+                    token: test_env.token,
+                    ticket_amount: 10_000_000,
+                    blend_address: test_env.blend_address,
+                    deployed_ledger: 100,
+                },
+                record
+            );
+            assert_eq!(1, test_env.factory_client.get_lottery_count());
+
+            assert_eq!(
+                vec![&e, last_event(&e, &test_env.factory_address)],
+                vec![
+                    &e,
+                    (
+                        test_env.factory_address.clone(),
+                        (Symbol::new(&e, "lottery_deployed"), address).into_val(&e),
+                        record.into_val(&e),
+                    )
+                ]
+            );
+        }
+
+        #[test]
+        fn deploys_distinct_lotteries() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let test_env = setup_test_env(&e);
+
+            let first = deploy(&test_env);
+            let second = deploy(&test_env);
+
+            assert_ne!(first, second);
+            assert_eq!(2, test_env.factory_client.get_lottery_count());
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #3)")]
+        fn zero_ticket_amount() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let test_env = setup_test_env(&e);
+
+            test_env.factory_client.deploy_lottery(
+                &test_env.lottery_admin,
+                &test_env.token,
+                &0,
+                &test_env.blend_address,
+                &default_phase_durations(),
+            );
+        }
+
+        // Rejected by the lottery constructor with its `InvalidPhaseDurations`
+        #[test]
+        #[should_panic(expected = "Error(Contract, #34)")]
+        fn invalid_phase_durations() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let test_env = setup_test_env(&e);
+
+            test_env.factory_client.deploy_lottery(
+                &test_env.lottery_admin,
+                &test_env.token,
+                &10_000_000,
+                &test_env.blend_address,
+                &PhaseDurations {
+                    reveal_ledgers: 20_000,
+                    ..default_phase_durations()
+                },
+            );
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #4)")]
+        fn token_not_in_blend_pool() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let test_env = setup_test_env(&e);
+
+            test_env.factory_client.deploy_lottery(
+                &test_env.lottery_admin,
+                &Address::generate(&e),
+                &10_000_000,
+                &test_env.blend_address,
+                &default_phase_durations(),
+            );
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Auth, InvalidAction)")]
+        fn requires_factory_admin() {
+            let e = Env::default();
+            let test_env = setup_test_env(&e);
+
+            deploy(&test_env);
+        }
+    }
+
+    mod registry {
+        use super::*;
+
+        #[test]
+        fn list_lotteries_paginated() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let test_env = setup_test_env(&e);
+
+            let first = deploy(&test_env);
+            let second = deploy(&test_env);
+            let third = deploy(&test_env);
+
+            let first_page = test_env.factory_client.list_lotteries(&1, &2);
+            let second_page = test_env.factory_client.list_lotteries(&3, &2);
+
+            assert_eq!(2, first_page.len());
+            assert_eq!(first, first_page.get_unchecked(0).address);
+            assert_eq!(second, first_page.get_unchecked(1).address);
+            assert_eq!(1, second_page.len());
+            assert_eq!(third, second_page.get_unchecked(0).address);
+            assert_eq!(3, second_page.get_unchecked(0).id);
+        }
+
+        #[test]
+        fn list_lotteries_empty() {
+            let e = Env::default();
+            let test_env = setup_test_env(&e);
+
+            assert_eq!(0, test_env.factory_client.list_lotteries(&1, &10).len());
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #5)")]
+        fn unknown_lottery() {
+            let e = Env::default();
+            let test_env = setup_test_env(&e);
+
+            test_env.factory_client.get_lottery(&1);
+        }
+
+        #[test]
+        fn set_wasm_hash() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let test_env = setup_test_env(&e);
+
+            let wasm_hash = BytesN::from_array(&e, &[1; 32]);
+            test_env.factory_client.set_wasm_hash(&wasm_hash);
+
+            assert!(e.auths().iter().any(|(authorizer, invocation)| {
+                *authorizer == test_env.admin
+                    && matches!(
+                        &invocation.function,
+                        AuthorizedFunction::Contract((_, name, _)) if *name == Symbol::new(&e, "set_wasm_hash")
+                    )
+            }));
+            assert_eq!(wasm_hash, test_env.factory_client.get_wasm_hash());
+        }
+    }
+
+    fn last_event(e: &Env, contract: &Address) -> (Address, Vec<Val>, Val) {
+        e.events()
+            .all()
+            .iter()
+            .filter(|event| event.0 == *contract)
+            .last()
+            .unwrap()
+    }
+
+    mod mock_blend {
+        use soroban_sdk::{contract, contractimpl, contracttype, Address, Env, Vec};
+
+        #[derive(Clone)]
+        #[contracttype]
+        enum MockKey {
+            Reserves,
+        }
+
+        // The factory only reads the reserve list of the Blend pool.
+        #[contract]
+        pub struct MockBlendPool;
+
+        #[contractimpl]
+        impl MockBlendPool {
+            pub fn set_reserve_list(e: Env, reserves: Vec<Address>) {
+                e.storage().instance().set(&MockKey::Reserves, &reserves);
+            }
+
+            pub fn get_reserve_list(e: Env) -> Vec<Address> {
+                e.storage()
+                    .instance()
+                    .get(&MockKey::Reserves)
+                    .unwrap_or(Vec::new(&e))
+            }
+        }
+    }
+
+    struct TestEnv<'a> {
+        admin: Address,
+        lottery_admin: Address,
+        token: Address,
+        blend_address: Address,
+        factory_address: Address,
+        factory_client: LotteryFactoryClient<'a>,
+    }
+
+    fn default_phase_durations() -> PhaseDurations {
+        PhaseDurations {
+            buyin_ledgers: 17_300,
+            farming_ledgers: 103_800,
+            ended_ledgers: 17_300,
+            reveal_ledgers: 8_650,
+        }
+    }
+
+    fn deploy(test_env: &TestEnv) -> Address {
+        test_env.factory_client.deploy_lottery(
+            &test_env.lottery_admin,
+            &test_env.token,
+            &10_000_000,
+            &test_env.blend_address,
+            &default_phase_durations(),
+        )
+    }
+
+    fn setup_test_env(e: &Env) -> TestEnv<'_> {
+        let admin = Address::generate(e);
+        let lottery_admin = Address::generate(e);
+
+        let token = e
+            .register_stellar_asset_contract_v2(admin.clone())
+            .address();
+
+        let blend_address = e.register(mock_blend::MockBlendPool, ());
+        mock_blend::MockBlendPoolClient::new(e, &blend_address)
+            .set_reserve_list(&vec![e, token.clone()]);
+
+        let wasm_hash = e.deployer().upload_contract_wasm(lottery::WASM);
+        let factory_address = e.register(LotteryFactory, (admin.clone(), wasm_hash));
+        let factory_client = LotteryFactoryClient::new(e, &factory_address);
+
+        TestEnv {
+            admin,
+            lottery_admin,
+            token,
+            blend_address,
+            factory_address,
+            factory_client,
+        }
+    }
+}
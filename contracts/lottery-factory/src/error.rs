@@ -0,0 +1,12 @@
+use soroban_sdk::contracterror;
+
+#[derive(Debug)]
+#[contracterror]
+#[repr(u32)]
+pub enum FactoryError {
+    AdminNotFound = 1,
+    WasmHashNotFound = 2,
+    InvalidAmount = 3,
+    TokenNotInBlendPool = 4,
+    LotteryNotFound = 5,
+}
@@ -0,0 +1,14 @@
+use crate::storage::LotteryRecord;
+use soroban_sdk::{Address, BytesN, Env, Symbol};
+
+pub fn lottery_deployed(e: &Env, lottery: &Address, record: &LotteryRecord) {
+    e.events().publish(
+        (Symbol::new(e, "lottery_deployed"), lottery.clone()),
+        record.clone(),
+    );
+}
+
+pub fn wasm_hash_updated(e: &Env, wasm_hash: &BytesN<32>) {
+    e.events()
+        .publish((Symbol::new(e, "wasm_hash_updated"),), wasm_hash.clone());
+}
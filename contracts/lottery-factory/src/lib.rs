@@ -0,0 +1,6 @@
+#![no_std]
+
+mod contract;
+mod error;
+mod events;
+mod storage;
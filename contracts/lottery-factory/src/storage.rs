@@ -0,0 +1,101 @@
+use crate::error::FactoryError;
+use soroban_sdk::{contracttype, Address, BytesN, Env, IntoVal, TryFromVal, Val};
+
+pub const MAX_LOTTERIES_PAGE_SIZE: u32 = 50;
+
+pub const DAY_IN_LEDGERS: u32 = 17_300;
+/// Entries are extended to live this many ledgers whenever they are read or
+/// written, so anything touched once a month never gets archived.
+pub const TTL_EXTEND_AMOUNT: u32 = 30 * DAY_IN_LEDGERS;
+pub const TTL_THRESHOLD: u32 = TTL_EXTEND_AMOUNT - DAY_IN_LEDGERS;
+
+/// Registry entry of a lottery deployed by the factory.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[contracttype]
+pub struct LotteryRecord {
+    pub id: u32,
+    pub address: Address,
+    pub admin: Address,
+    pub token: Address,
+    pub ticket_amount: i128,
+    pub blend_address: Address,
+    pub deployed_ledger: u32,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub enum Key {
+    Admin,
+    WasmHash,
+    LotteryCount,
+    Lottery(u32),
+}
+
+// Every access goes through these so reads and writes also bump the TTL.
+// Instance entries share one TTL with the contract instance and code.
+fn get_instance<V: TryFromVal<Env, Val>>(e: &Env, key: &Key) -> Option<V> {
+    extend_instance(e);
+    e.storage().instance().get(key)
+}
+
+fn set_instance<V: IntoVal<Env, Val>>(e: &Env, key: &Key, value: &V) {
+    e.storage().instance().set(key, value);
+    extend_instance(e);
+}
+
+fn get_persistent<V: TryFromVal<Env, Val>>(e: &Env, key: &Key) -> Option<V> {
+    let value = e.storage().persistent().get(key);
+    if value.is_some() {
+        extend_persistent(e, key);
+    }
+    value
+}
+
+fn set_persistent<V: IntoVal<Env, Val>>(e: &Env, key: &Key, value: &V) {
+    e.storage().persistent().set(key, value);
+    extend_persistent(e, key);
+}
+
+fn extend_persistent(e: &Env, key: &Key) {
+    e.storage()
+        .persistent()
+        .extend_ttl(key, TTL_THRESHOLD, TTL_EXTEND_AMOUNT);
+}
+
+pub fn extend_instance(e: &Env) {
+    e.storage()
+        .instance()
+        .extend_ttl(TTL_THRESHOLD, TTL_EXTEND_AMOUNT);
+}
+
+pub fn write_admin(e: &Env, admin: &Address) {
+    set_instance(e, &Key::Admin, admin);
+}
+
+pub fn read_admin(e: &Env) -> Option<Address> {
+    get_instance(e, &Key::Admin)
+}
+
+pub fn write_wasm_hash(e: &Env, wasm_hash: &BytesN<32>) {
+    set_instance(e, &Key::WasmHash, wasm_hash);
+}
+
+pub fn read_wasm_hash(e: &Env) -> Result<BytesN<32>, FactoryError> {
+    get_instance(e, &Key::WasmHash).ok_or(FactoryError::WasmHashNotFound)
+}
+
+pub fn write_lottery_count(e: &Env, count: u32) {
+    set_instance(e, &Key::LotteryCount, &count);
+}
+
+pub fn read_lottery_count(e: &Env) -> u32 {
+    get_instance(e, &Key::LotteryCount).unwrap_or(0)
+}
+
+pub fn write_lottery(e: &Env, record: &LotteryRecord) {
+    set_persistent(e, &Key::Lottery(record.id), record);
+}
+
+pub fn read_lottery(e: &Env, id: u32) -> Result<LotteryRecord, FactoryError> {
+    get_persistent(e, &Key::Lottery(id)).ok_or(FactoryError::LotteryNotFound)
+}
@@ -1,18 +1,48 @@
 use soroban_sdk::auth::{ContractContext, SubContractInvocation};
 use soroban_sdk::{
-    auth::InvokerContractAuthEntry, contract, contractimpl, token, vec, Address, Env,
+    auth::InvokerContractAuthEntry, contract, contractimpl, panic_with_error, token, vec, Address,
+    Bytes, BytesN, Env, Map, String,
 };
 use soroban_sdk::{IntoVal, Symbol};
+use stellar_default_impl_macro::default_impl;
+use stellar_non_fungible::{
+    enumerable::{Enumerable, NonFungibleEnumerable},
+    Base, NonFungibleToken,
+};
+use stellar_pausable::{self as pausable, Pausable};
+use stellar_pausable_macros::when_not_paused;
 
 use crate::error::LotteryError;
-use crate::storage::{LotteryState, LotteryStatus, Ticket};
+use crate::storage::{
+    AdvanceStep, Commitment, EmissionsConfig, EmissionsMode, LotteryState, LotteryStatus,
+    PendingPhaseDurations, PendingUpgrade, PhaseDurations, Pool, PrizeTier, ProtocolFee, Role,
+    RoundRandomness, RoundRecord, Ticket, TicketMetadata, TicketOdds,
+};
 use crate::util::generate_and_write_seed;
-use crate::{storage, util};
+use crate::{events, storage, util};
 
 mod blend {
     soroban_sdk::contractimport!(file = "../wasm/pool.wasm");
 }
 
+mod router {
+    use soroban_sdk::{contractclient, Address, Env};
+
+    /// Router that swaps the emissions. It pulls `amount_in` of `token_in`
+    /// from `from` and sends at least `min_amount_out` of `token_out` back.
+    #[contractclient(name = "Client")]
+    pub trait Router {
+        fn swap(
+            e: Env,
+            from: Address,
+            token_in: Address,
+            token_out: Address,
+            amount_in: i128,
+            min_amount_out: i128,
+        ) -> i128;
+    }
+}
+
 #[contract]
 struct NoLossLottery;
 
@@ -24,19 +54,45 @@ impl NoLossLottery {
         token: Address,
         ticket_amount: i128,
         blend_address: Address,
+        phase_durations: PhaseDurations,
     ) {
+        if let Err(error) = util::validate_phase_durations(&phase_durations) {
+            panic_with_error!(&e, error);
+        }
+
         storage::write_admin(&e, &admin);
         storage::write_lottery_status(&e, &LotteryStatus::BuyIn);
         storage::write_currency(&e, &token);
-        storage::write_token_amount(&e, &ticket_amount);
+        storage::write_pool(&e, &Self::new_pool(&token, ticket_amount));
+        storage::write_pool_tokens(&e, &vec![&e, token.clone()]);
         storage::write_blend_address(&e, &blend_address);
-        storage::write_sent_balance(&e, &0_i128);
+        if let Err(error) = Self::resolve_reserve_index(&e, &token) {
+            panic_with_error!(&e, error);
+        }
+        storage::write_phase_durations(&e, &phase_durations);
         storage::write_winner_selected(&e, false);
+        storage::write_prize_tiers(
+            &e,
+            &vec![
+                &e,
+                PrizeTier {
+                    share_bps: storage::BPS_DENOMINATOR,
+                },
+            ],
+        );
+
+        storage::write_current_round(&e, &util::new_round_record(&e, 1));
+        storage::write_schema_version(&e, storage::SCHEMA_VERSION);
+        Base::set_metadata(
+            &e,
+            String::from_str(&e, storage::TICKET_BASE_URI),
+            String::from_str(&e, storage::TICKET_NAME),
+            String::from_str(&e, storage::TICKET_SYMBOL),
+        );
 
         let initial_state = LotteryState {
             status: LotteryStatus::BuyIn,
             no_participants: 0,
-            amount_of_yield: 0,
             token: token.clone(),
             in_blender: false,
         };
@@ -44,60 +100,101 @@ impl NoLossLottery {
     }
 
     pub fn buy_ticket(e: Env, user: Address) -> Result<Ticket, LotteryError> {
+        let token = storage::read_currency(&e)?;
+        Self::buy_pool_ticket(e, user, token)
+    }
+
+    #[when_not_paused]
+    pub fn buy_pool_ticket(e: Env, user: Address, token: Address) -> Result<Ticket, LotteryError> {
         user.require_auth();
+        util::require_deposits_open(&e)?;
 
-        if storage::read_lottery_status(&e)? != LotteryStatus::BuyIn {
-            return Err(LotteryError::WrongStatus);
-        }
+        let pool = storage::read_pool(&e, &token)?;
+        let ticket = Self::issue_ticket(&e, &user, &token, pool.ticket_amount)?;
+
+        events::ticket_bought(&e, &user, ticket.id, ticket.amount);
+        Ok(ticket)
+    }
 
+    pub fn deposit(e: Env, user: Address, amount: i128) -> Result<Ticket, LotteryError> {
         let token = storage::read_currency(&e)?;
-        let amount = storage::read_token_amount(&e)?;
-        let token_client = token::Client::new(&e, &token);
-        token_client.transfer(&user, &e.current_contract_address(), &amount);
+        Self::deposit_to_pool(e, user, token, amount)
+    }
 
-        let ticket_id = storage::get_and_increment_ticket_counter(&e);
+    #[when_not_paused]
+    pub fn deposit_to_pool(
+        e: Env,
+        user: Address,
+        token: Address,
+        amount: i128,
+    ) -> Result<Ticket, LotteryError> {
+        user.require_auth();
+        util::require_deposits_open(&e)?;
 
-        let ticket = Ticket {
-            id: ticket_id,
-            user: user.clone(),
-            token,
-            amount,
-            won: false,
-        };
+        if amount <= 0 {
+            return Err(LotteryError::InvalidAmount);
+        }
+        storage::read_pool(&e, &token)?;
 
-        storage::write_ticket(&e, &ticket);
+        // Top up the user's position, its odds grow with the principal
+        if let Some(ticket_id) = storage::read_position(&e, &user, &token) {
+            let mut ticket = storage::read_ticket(&e, ticket_id)?;
+            Self::take_principal(&e, &user, &token, amount)?;
 
-        // Check if this is the user's first ticket
-        let user_tickets_before = storage::get_user_tickets(&e, &user);
-        storage::add_ticket_to_user(&e, &user, ticket_id);
+            ticket.amount += amount;
+            ticket.principal += amount;
+            storage::update_ticket(&e, &ticket);
+            storage::add_ticket_weight(&e, &ticket, amount)?;
 
-        if user_tickets_before.is_empty() {
-            // First ticket for this user - increment unique participants
-            let mut state = storage::read_lottery_state(&e)?;
-            state.no_participants += 1;
-            storage::write_lottery_state(&e, &state);
+            events::deposited(&e, &user, ticket.id, amount);
+            return Ok(ticket);
         }
 
+        let ticket = Self::issue_ticket(&e, &user, &token, amount)?;
+        storage::write_position(&e, &user, &token, ticket.id);
+
+        events::deposited(&e, &user, ticket.id, amount);
         Ok(ticket)
     }
 
-    pub fn redeem_ticket(e: Env, ticket: Ticket) -> Result<(), LotteryError> {
-        ticket.user.require_auth();
-
+    pub fn redeem_ticket_by_id(e: Env, user: Address, ticket_id: u32) -> Result<(), LotteryError> {
         let state = storage::read_lottery_state(&e)?;
-        let token_client = token::Client::new(&e, &ticket.token);
 
-        if state.status == LotteryStatus::YieldFarming || state.in_blender {
+        // While paused, tickets can be redeemed as soon as the funds are out of
+        // Blend. In rolling mode they are taken out of Blend when needed.
+        let is_farming = state.status == LotteryStatus::YieldFarming && !pausable::paused(&e);
+        if (is_farming || state.in_blender) && !storage::read_rolling_mode(&e) {
             return Err(LotteryError::WrongStatus);
         }
 
-        token_client.transfer(&e.current_contract_address(), &ticket.user, &ticket.amount);
-        storage::remove_ticket_from_user(&e, &ticket.user, ticket.id);
-        storage::remove_ticket(&e, ticket.clone());
+        // Only the stored ticket is trusted for the payout amount and token
+        let ticket = storage::read_ticket(&e, ticket_id)?;
+        if Base::owner_of(&e, ticket_id) != user {
+            return Err(LotteryError::NotAuthorized);
+        }
+
+        // Burning the NFT requires auth from `user`
+        Enumerable::non_sequential_burn(&e, &user, ticket.id);
+
+        // Only the principal is supplied to Blend, a prize is held by the
+        // contract
+        if state.in_blender {
+            Self::withdraw_for_redemption(&e, &ticket.token, ticket.principal)?;
+        }
+        let token_client = token::Client::new(&e, &ticket.token);
+        token_client.transfer(&e.current_contract_address(), &user, &ticket.amount);
+        let mut pool = storage::read_pool(&e, &ticket.token)?;
+        pool.principal -= ticket.principal;
+        pool.unclaimed_prizes -= ticket.amount - ticket.principal;
+        storage::write_pool(&e, &pool);
+        events::ticket_redeemed(&e, &user, ticket.id, ticket.amount);
+        if storage::read_position(&e, &user, &ticket.token) == Some(ticket.id) {
+            storage::remove_position(&e, &user, &ticket.token);
+        }
+        storage::remove_ticket(&e, ticket)?;
 
         // Check if user has no more tickets
-        let user_tickets = storage::get_user_tickets(&e, &ticket.user);
-        if user_tickets.is_empty() {
+        if Base::balance(&e, &user) == 0 {
             // User has no more tickets - decrement unique participants
             let mut state = storage::read_lottery_state(&e)?;
             state.no_participants -= 1;
@@ -107,8 +204,80 @@ impl NoLossLottery {
         Ok(())
     }
 
-    pub fn raffle(e: Env) -> Result<Ticket, LotteryError> {
-        let mut lottery_state = storage::read_lottery_state(&e)?;
+    pub fn commit(e: Env, committer: Address, commitment: BytesN<32>) -> Result<(), LotteryError> {
+        committer.require_auth();
+
+        if storage::read_lottery_status(&e)? != LotteryStatus::BuyIn {
+            return Err(LotteryError::WrongStatus);
+        }
+
+        // Only ticket holders and designated keepers contribute randomness
+        if Base::balance(&e, &committer) == 0 && !storage::has_role(&e, &Role::Keeper, &committer) {
+            return Err(LotteryError::NotAuthorized);
+        }
+
+        let round_id = storage::read_current_round(&e)?.round_id;
+        if storage::read_commitment(&e, round_id, &committer).is_some() {
+            return Err(LotteryError::AlreadyCommitted);
+        }
+
+        storage::write_commitment(
+            &e,
+            round_id,
+            &committer,
+            &Commitment {
+                hash: commitment.clone(),
+                revealed: false,
+            },
+        );
+        // The forfeit for withholding follows the tickets held now
+        for ticket_id in util::owner_ticket_ids(&e, &committer).iter() {
+            storage::write_ticket_committer(&e, round_id, ticket_id, &committer);
+        }
+        let mut randomness = storage::read_randomness(&e, round_id);
+        randomness.commitments += 1;
+        storage::write_randomness(&e, round_id, &randomness);
+        events::committed(&e, &committer, &commitment);
+        Ok(())
+    }
+
+    pub fn reveal(e: Env, committer: Address, secret: BytesN<32>) -> Result<(), LotteryError> {
+        committer.require_auth();
+
+        if storage::read_lottery_status(&e)? != LotteryStatus::Ended {
+            return Err(LotteryError::WrongStatus);
+        }
+
+        if storage::read_winner_selected(&e)? {
+            return Err(LotteryError::WinnerAlreadySelected);
+        }
+
+        let round_id = storage::read_current_round(&e)?.round_id;
+        let mut commitment = storage::read_commitment(&e, round_id, &committer)
+            .ok_or(LotteryError::CommitmentNotFound)?;
+
+        if commitment.revealed {
+            return Err(LotteryError::AlreadyRevealed);
+        }
+
+        let secret_hash: BytesN<32> = e.crypto().sha256(&Bytes::from(secret.clone())).into();
+        if secret_hash != commitment.hash {
+            return Err(LotteryError::InvalidReveal);
+        }
+
+        commitment.revealed = true;
+        storage::write_commitment(&e, round_id, &committer, &commitment);
+        let mut randomness = storage::read_randomness(&e, round_id);
+        randomness.reveals += 1;
+        randomness.mix = util::xor_secret(&e, &randomness.mix, &secret);
+        storage::write_randomness(&e, round_id, &randomness);
+        events::revealed(&e, &committer, &secret);
+        Ok(())
+    }
+
+    #[when_not_paused]
+    pub fn raffle(e: Env) -> Result<soroban_sdk::Vec<Ticket>, LotteryError> {
+        let lottery_state = storage::read_lottery_state(&e)?;
         if storage::read_lottery_status(&e)? != LotteryStatus::Ended {
             return Err(LotteryError::WrongStatus);
         }
@@ -121,30 +290,119 @@ impl NoLossLottery {
             return Err(LotteryError::BalancesInBlender);
         }
 
-        let active_ids = storage::read_ids(&e)?;
-        let seed_bytes = storage::read_seed(&e)?;
-        let prng = e.prng();
-        prng.seed(seed_bytes);
-        let winner_id_index: u64 = prng.gen_range(0..active_ids.len() as u64);
+        if !util::is_reveal_phase_over(&e)? {
+            return Err(LotteryError::RevealWindowOpen);
+        }
+
+        if util::active_ticket_total(&e) == 0 {
+            return Err(LotteryError::NoTicketsInRound);
+        }
+
+        let seed_bytes = util::mix_reveals(&e, &storage::read_seed(&e)?)?;
+        storage::write_seed(&e, &seed_bytes);
+        e.prng().seed(seed_bytes.clone());
+
+        let tiers = storage::read_prize_tiers(&e)?;
+        let round_id = storage::read_current_round(&e)?.round_id;
+        let odds_ledger = util::odds_ledger(&e)?;
+        let mut winners = soroban_sdk::Vec::new(&e);
+        let mut winning_ticket_ids = soroban_sdk::Vec::new(&e);
+        let mut pools_drawn = false;
+        // Every pool draws its own winners from its own tickets and pays
+        // them in its own token
+        for mut pool in util::read_pools(&e)?.iter() {
+            let no_tickets = storage::read_active_ticket_count(&e, &pool.token);
+            let prize_pot = pool.amount_of_yield + pool.jackpot;
+            // A pot below the minimum is kept for a later round
+            if no_tickets == 0 || prize_pot < pool.min_prize {
+                continue;
+            }
+
+            // Draw without replacement, with odds proportional to the
+            // principal times the ledgers it was held. Drawn tickets are
+            // zeroed in memory only, the stored trees are kept. A ticket
+            // bought at `odds_ledger` weighs nothing, so drawing stops once
+            // only such tickets are left. Tickets of a committer who withheld
+            // their reveal are drawn and dropped.
+            let max_winners = tiers.len().min(no_tickets);
+            let mut weight_deltas: Map<u32, i128> = Map::new(&e);
+            let mut pool_winners: soroban_sdk::Vec<Ticket> = soroban_sdk::Vec::new(&e);
+            while pool_winners.len() < max_winners {
+                let total_weight =
+                    util::total_weight(&e, &pool.token, &weight_deltas, odds_ledger, no_tickets);
+                if total_weight <= 0 {
+                    break;
+                }
+                pools_drawn = true;
+                let target = util::draw_below(&e, total_weight);
+                let winner_index = util::find_weighted_ticket(
+                    &e,
+                    &pool.token,
+                    &weight_deltas,
+                    odds_ledger,
+                    no_tickets,
+                    target,
+                );
+                let winner_id = storage::read_active_ticket(&e, &pool.token, winner_index)?;
+
+                let winner_ticket = storage::read_ticket(&e, winner_id)?;
+                let winner_weight = util::time_weight(
+                    winner_ticket.principal,
+                    storage::read_ticket_entry(&e, winner_id),
+                    odds_ledger,
+                );
+                util::exclude_ticket_weight(
+                    &mut weight_deltas,
+                    no_tickets,
+                    winner_index,
+                    winner_weight,
+                );
+                if util::has_withheld_reveal(&e, round_id, &winner_ticket) {
+                    continue;
+                }
+                pool_winners.push_back(winner_ticket);
+            }
+
+            // Without drawable tickets the pot stays for a later round
+            if pool_winners.is_empty() {
+                continue;
+            }
 
-        let winner_id = active_ids
-            .get(winner_id_index as u32)
-            .ok_or(LotteryError::TicketNotFound)?;
+            // Shares of undrawn tiers go to the first winner
+            let prizes = util::split_prize(&e, prize_pot, &tiers, pool_winners.len());
+            for (mut winner_ticket, prize) in pool_winners.iter().zip(prizes.iter()) {
+                winner_ticket.won = true;
+                winner_ticket.amount += prize;
+                pool.unclaimed_prizes += prize;
+                storage::update_ticket(&e, &winner_ticket);
+                events::winner_drawn(&e, &winner_ticket.user, round_id, winner_ticket.id, prize);
+                winning_ticket_ids.push_back(winner_ticket.id);
+                winners.push_back(winner_ticket);
+            }
 
-        let prize = lottery_state.amount_of_yield;
+            util::update_pool_record(&e, &pool.token, |record| record.prize_pot = prize_pot)?;
+            pool.amount_of_yield = 0;
+            pool.jackpot = 0;
+            storage::write_pool(&e, &pool);
+        }
 
-        let mut winner_ticket = storage::read_ticket(&e, winner_id)?;
-        winner_ticket.won = true;
-        winner_ticket.amount += prize;
-        storage::write_ticket(&e, &winner_ticket);
+        if !pools_drawn {
+            return Err(LotteryError::PrizeBelowMinimum);
+        }
         storage::write_winner_selected(&e, true);
+        if !winners.is_empty() {
+            Self::award_emissions(&e, round_id, &winners.get_unchecked(0));
+        }
 
-        lottery_state.amount_of_yield = 0;
-        storage::write_lottery_state(&e, &lottery_state);
+        let mut round = storage::read_current_round(&e)?;
+        round.winning_ticket_ids = winning_ticket_ids;
+        round.seed = seed_bytes;
+        storage::write_current_round(&e, &round);
 
-        Ok(winner_ticket)
+        Ok(winners)
     }
 
+    #[when_not_paused]
     pub fn set_status(e: Env, new_status: LotteryStatus) -> Result<(), LotteryError> {
         let current_status = storage::read_lottery_status(&e)?;
 
@@ -164,7 +422,30 @@ impl NoLossLottery {
         }
 
         if new_status == LotteryStatus::BuyIn {
+            if storage::read_lottery_state(&e)?.in_blender {
+                return Err(LotteryError::BalancesInBlender);
+            }
+
+            // Pools that drew no winner carry their yield over into the jackpot
+            let round_id = storage::read_current_round(&e)?.round_id;
+            for mut pool in util::read_pools(&e)?.iter() {
+                if pool.amount_of_yield != 0 {
+                    pool.jackpot += pool.amount_of_yield;
+                    events::prize_rolled_over(
+                        &e,
+                        &pool.token,
+                        round_id,
+                        pool.amount_of_yield,
+                        pool.jackpot,
+                    );
+
+                    pool.amount_of_yield = 0;
+                    storage::write_pool(&e, &pool);
+                }
+            }
+
             storage::write_winner_selected(&e, false);
+            util::apply_pending_phase_durations(&e);
         }
 
         if new_status == LotteryStatus::Ended {
@@ -176,446 +457,5320 @@ impl NoLossLottery {
             storage::write_farming_started_ledger(&e, current_ledger);
         }
 
+        util::track_round_transition(&e, &new_status)?;
+
         storage::write_lottery_status(&e, &new_status);
 
         let mut state = storage::read_lottery_state(&e)?;
-        state.status = new_status;
+        state.status = new_status.clone();
         storage::write_lottery_state(&e, &state);
 
+        events::status_changed(&e, &current_status, &new_status);
+
         Ok(())
     }
 
-    pub fn get_lottery_state(e: Env) -> Result<LotteryState, LotteryError> {
-        storage::read_lottery_state(&e)
-    }
+    #[when_not_paused]
+    pub fn advance(e: Env, caller: Address) -> Result<AdvanceStep, LotteryError> {
+        util::require_role(&e, &caller, &Role::Keeper)?;
 
-    pub fn get_admin(e: Env) -> Option<Address> {
-        storage::read_admin(&e)
-    }
+        let step = util::next_advance_step(&e)?;
 
-    pub fn get_ticket_amount(e: Env) -> Result<i128, LotteryError> {
-        storage::read_token_amount(&e)
-    }
+        // One bounty per round, paid from the yield as it is realized
+        let mut bounty = 0;
+        match step {
+            AdvanceStep::StartFarming => Self::set_status(e.clone(), LotteryStatus::YieldFarming)?,
+            AdvanceStep::DepositToBlend => Self::deposit_to_blend(&e)?,
+            AdvanceStep::EndFarming => Self::set_status(e.clone(), LotteryStatus::Ended)?,
+            AdvanceStep::WithdrawFromBlend => {
+                bounty = Self::withdraw_all_from_blend(&e, Some(&caller))?;
+            }
+            AdvanceStep::Raffle => {
+                Self::raffle(e.clone())?;
+            }
+            AdvanceStep::StartBuyIn => Self::set_status(e.clone(), LotteryStatus::BuyIn)?,
+        }
 
-    pub fn get_contract_balance(e: Env) -> Result<i128, LotteryError> {
-        let token_address = storage::read_currency(&e)?;
-        let token_client = token::Client::new(&e, &token_address);
-        Ok(token_client.balance(&e.current_contract_address()))
+        events::advanced(&e, &caller, &step, bounty);
+        Ok(step)
     }
 
-    pub fn get_user_tickets(
-        e: Env,
-        user: Address,
-    ) -> Result<soroban_sdk::Vec<Ticket>, LotteryError> {
-        let ticket_ids = storage::get_user_tickets(&e, &user);
-        let mut tickets = soroban_sdk::Vec::new(&e);
+    pub fn set_keeper_bounty(e: Env, amount: i128) -> Result<(), LotteryError> {
+        let admin = storage::read_admin(&e).ok_or(LotteryError::AdminNotFound)?;
+        admin.require_auth();
 
-        for ticket_id in ticket_ids.iter() {
-            match storage::read_ticket(&e, ticket_id) {
-                Ok(ticket) => tickets.push_back(ticket),
-                Err(_) => continue, // Skip tickets that can't be read
-            }
+        if amount < 0 {
+            return Err(LotteryError::InvalidAmount);
         }
 
-        Ok(tickets)
+        storage::write_keeper_bounty(&e, &amount);
+        events::keeper_bounty_updated(&e, amount);
+        Ok(())
     }
 
-    pub fn get_current_ledger(e: Env) -> u32 {
-        e.ledger().sequence()
+    pub fn get_keeper_bounty(e: Env) -> i128 {
+        storage::read_keeper_bounty(&e)
     }
 
-    pub fn get_status_started_ledger(e: Env) -> Result<u32, LotteryError> {
-        let status = storage::read_lottery_status(&e)?;
-        match status {
-            LotteryStatus::BuyIn => storage::read_buyin_started_ledger(&e),
-            LotteryStatus::YieldFarming => storage::read_farming_started_ledger(&e),
-            LotteryStatus::Ended => storage::read_ended_started_ledger(&e),
-        }
-    }
+    pub fn set_prize_tiers(e: Env, tiers: soroban_sdk::Vec<PrizeTier>) -> Result<(), LotteryError> {
+        let admin = storage::read_admin(&e).ok_or(LotteryError::AdminNotFound)?;
+        admin.require_auth();
 
-    pub fn blend_it(e: Env) -> Result<(), LotteryError> {
-        if storage::read_lottery_status(&e)? != LotteryStatus::YieldFarming {
+        // The distribution is fixed once the round starts farming
+        if storage::read_lottery_status(&e)? != LotteryStatus::BuyIn {
             return Err(LotteryError::WrongStatus);
         }
-        let admin = storage::read_admin(&e).ok_or(LotteryError::AdminNotFound)?;
-        admin.require_auth();
-        let token_address = storage::read_currency(&e)?;
-        let token_client = token::Client::new(&e, &token_address);
-        let contract_balance = token_client.balance(&e.current_contract_address());
 
-        let blend_address = storage::read_blend_address(&e)?;
-        let blend_client = blend::Client::new(&e, &blend_address);
+        util::validate_prize_tiers(&tiers)?;
+        storage::write_prize_tiers(&e, &tiers);
+        events::prize_tiers_updated(&e, &tiers);
+        Ok(())
+    }
 
-        let deposit_request = blend::Request {
-            address: token_address.clone(),
-            amount: contract_balance,
-            request_type: 0,
-        };
+    pub fn get_prize_tiers(e: Env) -> Result<soroban_sdk::Vec<PrizeTier>, LotteryError> {
+        storage::read_prize_tiers(&e)
+    }
 
-        let token_transfer_context = ContractContext {
-            contract: token_address.clone(),
-            fn_name: Symbol::new(&e, "transfer"),
-            args: vec![
-                &e,
-                e.current_contract_address().into_val(&e),
-                blend_address.clone().into_val(&e),
-                contract_balance.into_val(&e),
-            ],
-        };
+    pub fn set_min_prize(e: Env, token: Address, amount: i128) -> Result<(), LotteryError> {
+        let admin = storage::read_admin(&e).ok_or(LotteryError::AdminNotFound)?;
+        admin.require_auth();
 
-        let token_transfer_invocation = SubContractInvocation {
-            context: token_transfer_context,
-            sub_invocations: vec![&e],
-        };
+        if amount < 0 {
+            return Err(LotteryError::InvalidAmount);
+        }
 
-        e.authorize_as_current_contract(vec![
-            &e,
-            InvokerContractAuthEntry::Contract(token_transfer_invocation),
-        ]);
+        let mut pool = storage::read_pool(&e, &token)?;
+        pool.min_prize = amount;
+        storage::write_pool(&e, &pool);
+        events::min_prize_updated(&e, &token, amount);
+        Ok(())
+    }
 
-        blend_client.submit(
-            &e.current_contract_address(),
-            &e.current_contract_address(),
-            &e.current_contract_address(),
-            &vec![&e, deposit_request],
-        );
+    pub fn get_min_prize(e: Env, token: Address) -> Result<i128, LotteryError> {
+        Ok(storage::read_pool(&e, &token)?.min_prize)
+    }
 
-        // Track the amount sent to Blend for yield calculation
-        storage::write_sent_balance(&e, &contract_balance);
-        let mut lottery_state = storage::read_lottery_state(&e)?;
-        lottery_state.in_blender = true;
-        storage::write_lottery_state(&e, &lottery_state);
-        Ok(())
+    pub fn get_jackpot(e: Env, token: Address) -> Result<i128, LotteryError> {
+        Ok(storage::read_pool(&e, &token)?.jackpot)
     }
 
-    pub fn withdraw_from_blend(e: Env) -> Result<i128, LotteryError> {
-        if storage::read_lottery_status(&e)? != LotteryStatus::Ended {
+    pub fn get_prize_pot(e: Env, token: Address) -> Result<i128, LotteryError> {
+        let pool = storage::read_pool(&e, &token)?;
+        Ok(pool.amount_of_yield + pool.jackpot)
+    }
+
+    pub fn add_pool(e: Env, token: Address, ticket_amount: i128) -> Result<Pool, LotteryError> {
+        let admin = storage::read_admin(&e).ok_or(LotteryError::AdminNotFound)?;
+        admin.require_auth();
+
+        // A pool joins a round before any of its funds can be farmed
+        if storage::read_lottery_status(&e)? != LotteryStatus::BuyIn
+            || storage::read_lottery_state(&e)?.in_blender
+        {
             return Err(LotteryError::WrongStatus);
         }
-        let token_address = storage::read_currency(&e)?;
-        let token_client = token::Client::new(&e, &token_address);
-        let contract_balance_before = token_client.balance(&e.current_contract_address());
 
-        let blend_address = storage::read_blend_address(&e)?;
-        let blend_client = blend::Client::new(&e, &blend_address);
+        if ticket_amount <= 0 {
+            return Err(LotteryError::InvalidAmount);
+        }
 
-        let reserve_list = blend_client.get_reserve_list();
-        let mut reserve_index: u32 = 0;
-        for (i, address) in reserve_list.iter().enumerate() {
-            if address == token_address {
-                reserve_index = i as u32;
-                break;
-            }
+        let mut tokens = storage::read_pool_tokens(&e);
+        if tokens.contains(&token) {
+            return Err(LotteryError::PoolAlreadyExists);
+        }
+        if tokens.len() >= storage::MAX_POOLS {
+            return Err(LotteryError::TooManyPools);
         }
 
-        let positions = blend_client.get_positions(&e.current_contract_address());
-        positions
-            .supply
-            .get(reserve_index)
-            .ok_or(LotteryError::BlendPositionNotFound)?;
+        Self::resolve_reserve_index(&e, &token)?;
+        let pool = Self::new_pool(&token, ticket_amount);
+        storage::write_pool(&e, &pool);
+        tokens.push_back(token.clone());
+        storage::write_pool_tokens(&e, &tokens);
 
-        let withdraw_request = blend::Request {
-            address: token_address.clone(),
-            amount: i128::MAX,
-            request_type: 1,
-        };
+        let mut round = storage::read_current_round(&e)?;
+        round.pools.push_back(util::new_pool_record(&token));
+        storage::write_current_round(&e, &round);
 
-        let token_transfer_context = ContractContext {
-            contract: token_address.clone(),
-            fn_name: Symbol::new(&e, "transfer"),
-            args: vec![
-                &e,
-                blend_address.clone().into_val(&e),
-                e.current_contract_address().into_val(&e),
-                i128::MAX.into_val(&e),
-            ],
-        };
+        events::pool_added(&e, &token, ticket_amount);
+        Ok(pool)
+    }
 
-        let token_transfer_invocation = SubContractInvocation {
-            context: token_transfer_context,
-            sub_invocations: vec![&e],
-        };
+    pub fn get_pools(e: Env) -> Result<soroban_sdk::Vec<Pool>, LotteryError> {
+        util::read_pools(&e)
+    }
 
-        e.authorize_as_current_contract(vec![
-            &e,
-            InvokerContractAuthEntry::Contract(token_transfer_invocation),
-        ]);
+    pub fn get_pool(e: Env, token: Address) -> Result<Pool, LotteryError> {
+        storage::read_pool(&e, &token)
+    }
 
-        blend_client.submit(
-            &e.current_contract_address(),
-            &e.current_contract_address(),
-            &e.current_contract_address(),
-            &vec![&e, withdraw_request],
-        );
+    /// The yield the pool's Blend position has accrued so far, after the
+    /// protocol fee, at the reserve's current `b_rate`. `0` while the funds
+    /// are out of Blend, as their yield is already in the prize pot.
+    pub fn get_pending_yield(e: Env, token: Address) -> Result<i128, LotteryError> {
+        let pool = storage::read_pool(&e, &token)?;
+        if !storage::read_lottery_state(&e)?.in_blender {
+            return Ok(0);
+        }
 
-        let contract_balance_after = token_client.balance(&e.current_contract_address());
-        let balance_from_blend = contract_balance_after - contract_balance_before;
+        let yield_gained = Self::blend_position_value(&e, &token)? - pool.sent_balance;
+        Ok(yield_gained - util::protocol_fee(&e, yield_gained))
+    }
 
-        let sent_to_blend = storage::read_sent_balance(&e)?;
-        let yield_gained = balance_from_blend - sent_to_blend;
+    /// Checks that every pool's token balance plus its Blend position covers
+    /// what the pool owes: the principal, unclaimed prizes, yield, jackpot
+    /// and accrued fees. Fails with `Insolvent` otherwise.
+    pub fn check_solvency(e: Env) -> Result<(), LotteryError> {
+        let in_blender = storage::read_lottery_state(&e)?.in_blender;
+        for pool in util::read_pools(&e)?.iter() {
+            let token_client = token::Client::new(&e, &pool.token);
+            let mut assets = token_client.balance(&e.current_contract_address());
+            if in_blender {
+                assets += Self::blend_position_value(&e, &pool.token)?;
+            }
 
-        let mut lottery_state = storage::read_lottery_state(&e)?;
-        lottery_state.amount_of_yield = yield_gained;
-        lottery_state.in_blender = false;
-        storage::write_lottery_state(&e, &lottery_state);
+            if assets < util::liabilities(&e, &pool) {
+                return Err(LotteryError::Insolvent);
+            }
+        }
+        Ok(())
+    }
 
-        Ok(yield_gained)
+    /// The cached index of the pool's token in the Blend pool's reserves.
+    pub fn get_reserve_index(e: Env, token: Address) -> Result<u32, LotteryError> {
+        storage::read_pool(&e, &token)?;
+        storage::read_reserve_index(&e, &token).ok_or(LotteryError::ReserveNotFound)
     }
 
-    pub fn admin_claim_emissions(e: &Env) -> Result<(), LotteryError> {
-        let admin = storage::read_admin(e).ok_or(LotteryError::AdminNotFound)?;
+    /// Checks the cached reserve index of every pool against the Blend pool
+    /// and updates the ones whose reserve moved.
+    pub fn refresh_reserve_indexes(e: Env) -> Result<(), LotteryError> {
+        for token in storage::read_pool_tokens(&e).iter() {
+            Self::resolve_reserve_index(&e, &token)?;
+        }
+        Ok(())
+    }
 
-        let token_address = storage::read_currency(e)?;
-        let blend_address = storage::read_blend_address(e)?;
-        let blend_client = blend::Client::new(e, &blend_address);
+    pub fn set_rolling_mode(e: Env, enabled: bool) -> Result<(), LotteryError> {
+        let admin = storage::read_admin(&e).ok_or(LotteryError::AdminNotFound)?;
+        admin.require_auth();
 
-        let reserve_list = blend_client.get_reserve_list();
-        let mut reserve_index: u32 = 0;
-        for (i, address) in reserve_list.iter().enumerate() {
-            if address == token_address {
-                reserve_index = i as u32;
-                break;
-            }
+        // Users who entered a round keep the exit rules it started with
+        if storage::read_lottery_status(&e)? != LotteryStatus::BuyIn {
+            return Err(LotteryError::WrongStatus);
         }
-        // bTokens reserve_id * 2 + 1, dTokens reserve_id * 2.
-        let reserve_token_id = reserve_index * 2 + 1;
-        let reserve_ids = vec![&e, reserve_token_id];
-        blend_client.claim(&e.current_contract_address(), &reserve_ids, &admin);
 
+        storage::write_rolling_mode(&e, enabled);
+        events::rolling_mode_updated(&e, enabled);
         Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use soroban_sdk::{
-        testutils::{Address as _, Ledger},
-        token::{StellarAssetClient, TokenClient},
-    };
+    pub fn get_rolling_mode(e: Env) -> bool {
+        storage::read_rolling_mode(&e)
+    }
 
-    use super::*;
+    pub fn set_protocol_fee(e: Env, fee_bps: u32, treasury: Address) -> Result<(), LotteryError> {
+        let admin = storage::read_admin(&e).ok_or(LotteryError::AdminNotFound)?;
+        admin.require_auth();
 
-    mod buy_tickets {
-        use super::*;
+        if fee_bps > storage::MAX_PROTOCOL_FEE_BPS {
+            return Err(LotteryError::InvalidProtocolFee);
+        }
+
+        let fee = ProtocolFee { fee_bps, treasury };
+        storage::write_protocol_fee(&e, &fee);
+        events::protocol_fee_updated(&e, &fee);
+        Ok(())
+    }
+
+    pub fn get_protocol_fee(e: Env) -> Option<ProtocolFee> {
+        storage::read_protocol_fee(&e)
+    }
+
+    pub fn get_accrued_fees(e: Env, token: Address) -> i128 {
+        storage::read_accrued_fees(&e, &token)
+    }
+
+    /// Sends the fees accrued in the pool of `token` to the treasury.
+    pub fn withdraw_fees(e: Env, token: Address) -> Result<i128, LotteryError> {
+        let fee = storage::read_protocol_fee(&e).ok_or(LotteryError::ProtocolFeeNotFound)?;
+        fee.treasury.require_auth();
+
+        storage::read_pool(&e, &token)?;
+        if storage::read_lottery_state(&e)?.in_blender {
+            return Err(LotteryError::BalancesInBlender);
+        }
+
+        let amount = storage::read_accrued_fees(&e, &token);
+        if amount > 0 {
+            token::Client::new(&e, &token).transfer(
+                &e.current_contract_address(),
+                &fee.treasury,
+                &amount,
+            );
+            storage::write_accrued_fees(&e, &token, 0);
+            events::fees_withdrawn(&e, &token, &fee.treasury, amount);
+        }
+        Ok(amount)
+    }
+
+    pub fn propose_phase_durations(
+        e: Env,
+        durations: PhaseDurations,
+    ) -> Result<PendingPhaseDurations, LotteryError> {
+        let admin = storage::read_admin(&e).ok_or(LotteryError::AdminNotFound)?;
+        admin.require_auth();
+
+        util::validate_phase_durations(&durations)?;
+
+        // Users get at least one full round with the current durations to react
+        let pending = PendingPhaseDurations {
+            durations,
+            unlock_ledger: e.ledger().sequence() + util::round_length(&e)?,
+        };
+        storage::write_pending_phase_durations(&e, &pending);
+
+        events::phase_durations_proposed(&e, &pending);
+        Ok(pending)
+    }
+
+    pub fn cancel_phase_durations(e: Env) -> Result<(), LotteryError> {
+        let admin = storage::read_admin(&e).ok_or(LotteryError::AdminNotFound)?;
+        admin.require_auth();
+
+        if storage::read_pending_phase_durations(&e).is_none() {
+            return Err(LotteryError::PendingPhaseDurationsNotFound);
+        }
+
+        storage::remove_pending_phase_durations(&e);
+        events::phase_durations_cancelled(&e);
+        Ok(())
+    }
+
+    pub fn get_phase_durations(e: Env) -> Result<PhaseDurations, LotteryError> {
+        storage::read_phase_durations(&e)
+    }
+
+    pub fn get_pending_phase_durations(e: Env) -> Option<PendingPhaseDurations> {
+        storage::read_pending_phase_durations(&e)
+    }
+
+    pub fn schedule_upgrade(
+        e: Env,
+        new_wasm_hash: BytesN<32>,
+    ) -> Result<PendingUpgrade, LotteryError> {
+        let admin = storage::read_admin(&e).ok_or(LotteryError::AdminNotFound)?;
+        admin.require_auth();
+
+        // Users get a full round to redeem before the new code takes over
+        let pending = PendingUpgrade {
+            wasm_hash: new_wasm_hash,
+            unlock_ledger: e.ledger().sequence() + util::round_length(&e)?,
+        };
+        storage::write_pending_upgrade(&e, &pending);
+
+        events::upgrade_scheduled(&e, &pending);
+        Ok(pending)
+    }
+
+    pub fn cancel_upgrade(e: Env) -> Result<(), LotteryError> {
+        let admin = storage::read_admin(&e).ok_or(LotteryError::AdminNotFound)?;
+        admin.require_auth();
+
+        if storage::read_pending_upgrade(&e).is_none() {
+            return Err(LotteryError::PendingUpgradeNotFound);
+        }
+
+        storage::remove_pending_upgrade(&e);
+        events::upgrade_cancelled(&e);
+        Ok(())
+    }
+
+    pub fn upgrade(e: Env, new_wasm_hash: BytesN<32>) -> Result<(), LotteryError> {
+        let admin = storage::read_admin(&e).ok_or(LotteryError::AdminNotFound)?;
+        admin.require_auth();
+
+        let pending = storage::read_pending_upgrade(&e)
+            .filter(|pending| pending.wasm_hash == new_wasm_hash)
+            .ok_or(LotteryError::PendingUpgradeNotFound)?;
+        if e.ledger().sequence() < pending.unlock_ledger {
+            return Err(LotteryError::MinimumTimeLockNotEnded);
+        }
+
+        storage::remove_pending_upgrade(&e);
+        events::upgraded(&e, &new_wasm_hash);
+        e.deployer().update_current_contract_wasm(new_wasm_hash);
+        Ok(())
+    }
+
+    pub fn get_schema_version(e: Env) -> u32 {
+        storage::read_schema_version(&e)
+    }
+
+    pub fn get_pending_upgrade(e: Env) -> Option<PendingUpgrade> {
+        storage::read_pending_upgrade(&e)
+    }
+
+    pub fn grant_role(e: Env, role: Role, account: Address) -> Result<(), LotteryError> {
+        let admin = storage::read_admin(&e).ok_or(LotteryError::AdminNotFound)?;
+        admin.require_auth();
+
+        let mut members = storage::read_role_members(&e, &role);
+        if !members.contains(&account) {
+            members.push_back(account.clone());
+            storage::write_role_members(&e, &role, &members);
+            events::role_granted(&e, &role, &account);
+        }
+        Ok(())
+    }
+
+    pub fn revoke_role(e: Env, role: Role, account: Address) -> Result<(), LotteryError> {
+        let admin = storage::read_admin(&e).ok_or(LotteryError::AdminNotFound)?;
+        admin.require_auth();
+
+        let mut members = storage::read_role_members(&e, &role);
+        if let Some(index) = members.first_index_of(&account) {
+            members.remove(index);
+            storage::write_role_members(&e, &role, &members);
+            events::role_revoked(&e, &role, &account);
+        }
+        Ok(())
+    }
+
+    pub fn get_role_members(e: Env, role: Role) -> soroban_sdk::Vec<Address> {
+        storage::read_role_members(&e, &role)
+    }
+
+    pub fn has_role(e: Env, role: Role, account: Address) -> bool {
+        storage::is_admin(&e, &account) || storage::has_role(&e, &role, &account)
+    }
+
+    pub fn get_commitment(e: Env, round_id: u32, committer: Address) -> Option<Commitment> {
+        storage::read_commitment(&e, round_id, &committer)
+    }
+
+    pub fn get_randomness(e: Env, round_id: u32) -> RoundRandomness {
+        storage::read_randomness(&e, round_id)
+    }
+
+    pub fn get_current_round(e: Env) -> Result<RoundRecord, LotteryError> {
+        storage::read_current_round(&e)
+    }
+
+    pub fn get_round(e: Env, round_id: u32) -> Result<RoundRecord, LotteryError> {
+        storage::read_round(&e, round_id)
+    }
+
+    pub fn list_rounds(e: Env, start_round_id: u32, limit: u32) -> soroban_sdk::Vec<RoundRecord> {
+        let mut rounds = soroban_sdk::Vec::new(&e);
+        let limit = limit.min(storage::MAX_ROUNDS_PAGE_SIZE);
+        for round_id in start_round_id..start_round_id.saturating_add(limit) {
+            match storage::read_round(&e, round_id) {
+                Ok(round) => rounds.push_back(round),
+                Err(_) => break, // Rounds are archived in order
+            }
+        }
+        rounds
+    }
+
+    pub fn get_lottery_state(e: Env) -> Result<LotteryState, LotteryError> {
+        storage::read_lottery_state(&e)
+    }
+
+    pub fn get_admin(e: Env) -> Option<Address> {
+        storage::read_admin(&e)
+    }
+
+    pub fn get_ticket_amount(e: Env) -> Result<i128, LotteryError> {
+        Ok(storage::read_pool(&e, &storage::read_currency(&e)?)?.ticket_amount)
+    }
+
+    pub fn get_contract_balance(e: Env) -> Result<i128, LotteryError> {
+        let token_address = storage::read_currency(&e)?;
+        let token_client = token::Client::new(&e, &token_address);
+        Ok(token_client.balance(&e.current_contract_address()))
+    }
+
+    pub fn get_user_tickets(
+        e: Env,
+        user: Address,
+    ) -> Result<soroban_sdk::Vec<Ticket>, LotteryError> {
+        let ticket_ids = util::owner_ticket_ids(&e, &user);
+        let mut tickets = soroban_sdk::Vec::new(&e);
+
+        for ticket_id in ticket_ids.iter() {
+            match storage::read_ticket(&e, ticket_id) {
+                Ok(ticket) => tickets.push_back(ticket),
+                Err(_) => continue, // Skip tickets that can't be read
+            }
+        }
+
+        Ok(tickets)
+    }
+
+    pub fn bump_ticket(e: Env, ticket_id: u32) -> Result<(), LotteryError> {
+        // Reading extends the TTL of the ticket, and the NFT library extends
+        // its owner and balance entries on read
+        let ticket = storage::read_ticket(&e, ticket_id)?;
+        Base::owner_of(&e, ticket_id);
+        Base::balance(&e, &ticket.user);
+        storage::extend_instance(&e);
+
+        // Redeeming and drawing the ticket also read the lottery, its pool
+        // and the ticket's entries in the pool's index and weight trees
+        storage::read_lottery_state(&e)?;
+        storage::read_pool(&e, &ticket.token)?;
+        storage::extend_ticket_entries(&e, &ticket)
+    }
+
+    pub fn get_ticket_metadata(e: Env, ticket_id: u32) -> Result<TicketMetadata, LotteryError> {
+        let ticket = storage::read_ticket(&e, ticket_id)?;
+        Ok(TicketMetadata {
+            round_id: ticket.round_id,
+            principal: ticket.principal,
+            won: ticket.won,
+        })
+    }
+
+    pub fn get_ticket_odds(e: Env, ticket_id: u32) -> Result<TicketOdds, LotteryError> {
+        let ticket = storage::read_ticket(&e, ticket_id)?;
+        let ledger = util::odds_ledger(&e)?;
+        let no_tickets = storage::read_active_ticket_count(&e, &ticket.token);
+        Ok(TicketOdds {
+            weight: util::time_weight(
+                ticket.principal,
+                storage::read_ticket_entry(&e, ticket_id),
+                ledger,
+            ),
+            total_weight: util::total_weight(&e, &ticket.token, &Map::new(&e), ledger, no_tickets),
+            ledger,
+        })
+    }
+
+    pub fn get_current_ledger(e: Env) -> u32 {
+        e.ledger().sequence()
+    }
+
+    pub fn get_status_started_ledger(e: Env) -> Result<u32, LotteryError> {
+        let status = storage::read_lottery_status(&e)?;
+        match status {
+            LotteryStatus::BuyIn => storage::read_buyin_started_ledger(&e),
+            LotteryStatus::YieldFarming => storage::read_farming_started_ledger(&e),
+            LotteryStatus::Ended => storage::read_ended_started_ledger(&e),
+        }
+    }
+
+    #[when_not_paused]
+    pub fn blend_it(e: Env, caller: Address) -> Result<(), LotteryError> {
+        util::require_role(&e, &caller, &Role::Operator)?;
+
+        if storage::read_lottery_status(&e)? != LotteryStatus::YieldFarming {
+            return Err(LotteryError::WrongStatus);
+        }
+
+        Self::deposit_to_blend(&e)
+    }
+
+    pub fn withdraw_from_blend(e: Env, caller: Address) -> Result<(), LotteryError> {
+        util::require_role(&e, &caller, &Role::Operator)?;
+
+        // Paused, the funds can be pulled out of Blend in any status
+        if storage::read_lottery_status(&e)? != LotteryStatus::Ended && !pausable::paused(&e) {
+            return Err(LotteryError::WrongStatus);
+        }
+
+        Self::withdraw_all_from_blend(&e, None)?;
+        Ok(())
+    }
+
+    pub fn admin_claim_emissions(e: &Env) -> Result<(), LotteryError> {
+        let admin = storage::read_admin(e).ok_or(LotteryError::AdminNotFound)?;
+        admin.require_auth();
+
+        if Self::emissions_mode(e) != EmissionsMode::Admin {
+            return Err(LotteryError::WrongEmissionsMode);
+        }
+
+        Self::claim_from_blend(e, &admin)?;
+        Ok(())
+    }
+
+    pub fn set_emissions_config(e: Env, config: EmissionsConfig) -> Result<(), LotteryError> {
+        let admin = storage::read_admin(&e).ok_or(LotteryError::AdminNotFound)?;
+        admin.require_auth();
+
+        if (config.mode == EmissionsMode::Swap && config.router.is_none())
+            || storage::read_pool_tokens(&e).contains(&config.blnd_token)
+        {
+            return Err(LotteryError::InvalidEmissionsConfig);
+        }
+
+        storage::write_emissions_config(&e, &config);
+        events::emissions_config_updated(&e, &config);
+        Ok(())
+    }
+
+    pub fn get_emissions_config(e: Env) -> Option<EmissionsConfig> {
+        storage::read_emissions_config(&e)
+    }
+
+    /// Claims the emissions to the contract in `Swap` and `Prize` mode. In
+    /// `Swap` mode every emission token the contract holds is swapped into
+    /// the first pool's token, for at least `min_amount_out`.
+    pub fn claim_emissions(
+        e: Env,
+        caller: Address,
+        min_amount_out: i128,
+    ) -> Result<i128, LotteryError> {
+        util::require_role(&e, &caller, &Role::Operator)?;
+
+        let config = storage::read_emissions_config(&e)
+            .filter(|config| config.mode != EmissionsMode::Admin)
+            .ok_or(LotteryError::WrongEmissionsMode)?;
+
+        let claimed = Self::claim_from_blend(&e, &e.current_contract_address())?;
+        if let (EmissionsMode::Swap, Some(router)) = (&config.mode, &config.router) {
+            Self::swap_emissions(&e, &config.blnd_token, router, min_amount_out)?;
+        }
+        Ok(claimed)
+    }
+}
+
+#[contractimpl]
+impl Pausable for NoLossLottery {
+    fn paused(e: &Env) -> bool {
+        pausable::paused(e)
+    }
+
+    fn pause(e: &Env, caller: Address) {
+        if !storage::is_admin(e, &caller) {
+            panic_with_error!(e, LotteryError::NotAuthorized);
+        }
+        caller.require_auth();
+
+        pausable::pause(e);
+    }
+
+    fn unpause(e: &Env, caller: Address) {
+        if !storage::is_admin(e, &caller) {
+            panic_with_error!(e, LotteryError::NotAuthorized);
+        }
+        caller.require_auth();
+
+        pausable::unpause(e);
+    }
+}
+
+#[default_impl]
+#[contractimpl]
+impl NonFungibleToken for NoLossLottery {
+    type ContractType = Enumerable;
+
+    fn transfer(e: &Env, from: Address, to: Address, token_id: u32) {
+        Enumerable::transfer(e, &from, &to, token_id);
+        if let Err(error) = util::track_ticket_transfer(e, &from, &to, token_id) {
+            panic_with_error!(e, error);
+        }
+    }
+
+    fn transfer_from(e: &Env, spender: Address, from: Address, to: Address, token_id: u32) {
+        Enumerable::transfer_from(e, &spender, &from, &to, token_id);
+        if let Err(error) = util::track_ticket_transfer(e, &from, &to, token_id) {
+            panic_with_error!(e, error);
+        }
+    }
+}
+
+#[default_impl]
+#[contractimpl]
+impl NonFungibleEnumerable for NoLossLottery {}
+
+impl NoLossLottery {
+    fn resolve_reserve_index(e: &Env, token: &Address) -> Result<u32, LotteryError> {
+        let blend_address = storage::read_blend_address(e)?;
+        let reserve_list = blend::Client::new(e, &blend_address).get_reserve_list();
+        util::reserve_index(e, &reserve_list, token)
+    }
+
+    fn new_pool(token: &Address, ticket_amount: i128) -> Pool {
+        Pool {
+            token: token.clone(),
+            ticket_amount,
+            min_prize: 0,
+            principal: 0,
+            unclaimed_prizes: 0,
+            sent_balance: 0,
+            amount_of_yield: 0,
+            jackpot: 0,
+        }
+    }
+
+    /// Takes `amount` of `token` from `user` and mints them a ticket NFT for
+    /// it in that token's pool.
+    fn issue_ticket(
+        e: &Env,
+        user: &Address,
+        token: &Address,
+        amount: i128,
+    ) -> Result<Ticket, LotteryError> {
+        Self::take_principal(e, user, token, amount)?;
+
+        let ticket_id = storage::get_and_increment_ticket_counter(e);
+        let round = storage::read_current_round(e)?;
+
+        let ticket = Ticket {
+            id: ticket_id,
+            user: user.clone(),
+            token: token.clone(),
+            amount,
+            won: false,
+            round_id: round.round_id,
+            principal: amount,
+        };
+
+        storage::write_ticket(e, &ticket);
+
+        // Check if this is the user's first ticket
+        let is_first_ticket = Base::balance(e, user) == 0;
+        Enumerable::non_sequential_mint(e, user, ticket_id);
+
+        if is_first_ticket {
+            // First ticket for this user - increment unique participants
+            let mut state = storage::read_lottery_state(e)?;
+            state.no_participants += 1;
+            storage::write_lottery_state(e, &state);
+        }
+
+        Ok(ticket)
+    }
+
+    /// Takes `amount` of `token` from `user` into the pool's principal.
+    fn take_principal(
+        e: &Env,
+        user: &Address,
+        token: &Address,
+        amount: i128,
+    ) -> Result<(), LotteryError> {
+        let token_client = token::Client::new(e, token);
+        token_client.transfer(user, &e.current_contract_address(), &amount);
+
+        let mut pool = storage::read_pool(e, token)?;
+        pool.principal += amount;
+        storage::write_pool(e, &pool);
+        Self::supply_if_in_blend(e, token, amount)
+    }
+
+    fn deposit_to_blend(e: &Env) -> Result<(), LotteryError> {
+        for mut pool in util::read_pools(e)?.iter() {
+            let amount = util::blend_amount(&pool);
+            if amount <= 0 {
+                continue;
+            }
+
+            Self::supply_to_blend(e, &pool.token, amount)?;
+
+            // Track the amount sent to Blend for yield calculation
+            pool.sent_balance = amount;
+            storage::write_pool(e, &pool);
+            util::update_pool_record(e, &pool.token, |record| record.total_deposited = amount)?;
+
+            events::deposited_to_blend(e, &pool.token, amount);
+        }
+
+        let mut lottery_state = storage::read_lottery_state(e)?;
+        lottery_state.in_blender = true;
+        storage::write_lottery_state(e, &lottery_state);
+        Ok(())
+    }
+
+    /// In rolling mode a deposit made while the funds are farming goes
+    /// straight into the pool's Blend position.
+    fn supply_if_in_blend(e: &Env, token: &Address, amount: i128) -> Result<(), LotteryError> {
+        if !storage::read_lottery_state(e)?.in_blender {
+            return Ok(());
+        }
+
+        Self::supply_to_blend(e, token, amount)?;
+
+        let mut pool = storage::read_pool(e, token)?;
+        pool.sent_balance += amount;
+        storage::write_pool(e, &pool);
+        util::update_pool_record(e, token, |record| record.total_deposited += amount)?;
+
+        events::deposited_to_blend(e, token, amount);
+        Ok(())
+    }
+
+    /// Value of the contract's position in the Blend reserve of `token` at
+    /// the reserve's current `b_rate`. Resolves the index without touching
+    /// the cache, so views can use it.
+    fn blend_position_value(e: &Env, token: &Address) -> Result<i128, LotteryError> {
+        let blend_address = storage::read_blend_address(e)?;
+        let blend_client = blend::Client::new(e, &blend_address);
+        let reserve_index = blend_client
+            .get_reserve_list()
+            .first_index_of(token)
+            .ok_or(LotteryError::ReserveNotFound)?;
+        let b_tokens = blend_client
+            .get_positions(&e.current_contract_address())
+            .supply
+            .get(reserve_index)
+            .unwrap_or(0);
+        let b_rate = blend_client.get_reserve(token).data.b_rate;
+        Ok(util::b_tokens_to_underlying(b_tokens, b_rate))
+    }
+
+    /// Takes a redeemed ticket's principal out of its pool's Blend position.
+    /// The yield stays in Blend for the draw.
+    fn withdraw_for_redemption(e: &Env, token: &Address, amount: i128) -> Result<(), LotteryError> {
+        let blend_address = storage::read_blend_address(e)?;
+        let blend_client = blend::Client::new(e, &blend_address);
+        let withdraw_request = blend::Request {
+            address: token.clone(),
+            amount,
+            request_type: 1,
+        };
+        blend_client.submit(
+            &e.current_contract_address(),
+            &e.current_contract_address(),
+            &e.current_contract_address(),
+            &vec![e, withdraw_request],
+        );
+
+        // Blend pays out exactly the requested amount or fails
+        let mut pool = storage::read_pool(e, token)?;
+        pool.sent_balance -= amount;
+        storage::write_pool(e, &pool);
+        Ok(())
+    }
+
+    fn supply_to_blend(e: &Env, token_address: &Address, amount: i128) -> Result<(), LotteryError> {
+        let blend_address = storage::read_blend_address(e)?;
+        let blend_client = blend::Client::new(e, &blend_address);
+
+        let deposit_request = blend::Request {
+            address: token_address.clone(),
+            amount,
+            request_type: 0,
+        };
+
+        let token_transfer_context = ContractContext {
+            contract: token_address.clone(),
+            fn_name: Symbol::new(e, "transfer"),
+            args: vec![
+                e,
+                e.current_contract_address().into_val(e),
+                blend_address.clone().into_val(e),
+                amount.into_val(e),
+            ],
+        };
+
+        let token_transfer_invocation = SubContractInvocation {
+            context: token_transfer_context,
+            sub_invocations: vec![e],
+        };
+
+        e.authorize_as_current_contract(vec![
+            e,
+            InvokerContractAuthEntry::Contract(token_transfer_invocation),
+        ]);
+
+        blend_client.submit(
+            &e.current_contract_address(),
+            &e.current_contract_address(),
+            &e.current_contract_address(),
+            &vec![e, deposit_request],
+        );
+        Ok(())
+    }
+
+    fn emissions_mode(e: &Env) -> EmissionsMode {
+        storage::read_emissions_config(e)
+            .map(|config| config.mode)
+            .unwrap_or(EmissionsMode::Admin)
+    }
+
+    /// Claims the emissions of every pool's supply position to `to` and adds
+    /// them to the current round.
+    fn claim_from_blend(e: &Env, to: &Address) -> Result<i128, LotteryError> {
+        let blend_address = storage::read_blend_address(e)?;
+        let blend_client = blend::Client::new(e, &blend_address);
+
+        let reserve_list = blend_client.get_reserve_list();
+        let mut reserve_ids = vec![e];
+        for token_address in storage::read_pool_tokens(e).iter() {
+            let reserve_index = util::reserve_index(e, &reserve_list, &token_address)?;
+            // bTokens reserve_id * 2 + 1, dTokens reserve_id * 2.
+            reserve_ids.push_back(reserve_index * 2 + 1);
+        }
+        let claimed = blend_client.claim(&e.current_contract_address(), &reserve_ids, to);
+
+        let mut round = storage::read_current_round(e)?;
+        round.emissions += claimed;
+        storage::write_current_round(e, &round);
+
+        events::emissions_claimed(e, to, claimed);
+        Ok(claimed)
+    }
+
+    /// Swaps the emission tokens the contract holds into the first pool's
+    /// token and adds them to its prize pot.
+    fn swap_emissions(
+        e: &Env,
+        blnd_token: &Address,
+        router_address: &Address,
+        min_amount_out: i128,
+    ) -> Result<(), LotteryError> {
+        let amount_in = token::Client::new(e, blnd_token).balance(&e.current_contract_address());
+        if amount_in == 0 {
+            return Ok(());
+        }
+
+        let mut pool = storage::read_pool(e, &storage::read_currency(e)?)?;
+        let token_client = token::Client::new(e, &pool.token);
+        let contract_balance_before = token_client.balance(&e.current_contract_address());
+
+        let token_transfer_context = ContractContext {
+            contract: blnd_token.clone(),
+            fn_name: Symbol::new(e, "transfer"),
+            args: vec![
+                e,
+                e.current_contract_address().into_val(e),
+                router_address.clone().into_val(e),
+                amount_in.into_val(e),
+            ],
+        };
+
+        let token_transfer_invocation = SubContractInvocation {
+            context: token_transfer_context,
+            sub_invocations: vec![e],
+        };
+
+        e.authorize_as_current_contract(vec![
+            e,
+            InvokerContractAuthEntry::Contract(token_transfer_invocation),
+        ]);
+
+        router::Client::new(e, router_address).swap(
+            &e.current_contract_address(),
+            blnd_token,
+            &pool.token,
+            &amount_in,
+            &min_amount_out,
+        );
+
+        // Trust the balance, not the router's return value
+        let amount_out =
+            token_client.balance(&e.current_contract_address()) - contract_balance_before;
+        if amount_out < min_amount_out {
+            return Err(LotteryError::SwapBelowMinimum);
+        }
+
+        pool.amount_of_yield += amount_out;
+        storage::write_pool(e, &pool);
+        events::emissions_swapped(e, &pool.token, amount_in, amount_out);
+        Ok(())
+    }
+
+    /// In `Prize` mode pays the emission tokens the contract holds to the
+    /// owner of the first winning ticket. A transfer the owner cannot receive,
+    /// e.g. without a trustline, leaves them for the next round.
+    fn award_emissions(e: &Env, round_id: u32, winner: &Ticket) {
+        let Some(config) = storage::read_emissions_config(e) else {
+            return;
+        };
+        if config.mode != EmissionsMode::Prize {
+            return;
+        }
+
+        let blnd_client = token::Client::new(e, &config.blnd_token);
+        let amount = blnd_client.balance(&e.current_contract_address());
+        if amount == 0 {
+            return;
+        }
+
+        if blnd_client
+            .try_transfer(&e.current_contract_address(), &winner.user, &amount)
+            .is_ok()
+        {
+            events::emissions_awarded(e, &winner.user, round_id, winner.id, amount);
+        }
+    }
+
+    /// Withdraws every pool from Blend. A `keeper` is paid the keeper bounty,
+    /// which is returned, from the yield of the pool of the lottery's
+    /// currency, the token the bounty is set in. Other pools pay none.
+    fn withdraw_all_from_blend(e: &Env, keeper: Option<&Address>) -> Result<i128, LotteryError> {
+        let blend_address = storage::read_blend_address(e)?;
+        let currency = storage::read_currency(e)?;
+        let blend_client = blend::Client::new(e, &blend_address);
+        let reserve_list = blend_client.get_reserve_list();
+        let positions = blend_client.get_positions(&e.current_contract_address());
+
+        let mut bounty = 0;
+        for pool in util::read_pools(e)?.iter() {
+            let reserve_index = util::reserve_index(e, &reserve_list, &pool.token)?;
+
+            // Pools without funds of their own never got a position
+            let Some(b_tokens) = positions.supply.get(reserve_index) else {
+                if pool.sent_balance > 0 {
+                    return Err(LotteryError::BlendPositionNotFound);
+                }
+                continue;
+            };
+
+            let pool_keeper = keeper.filter(|_| pool.token == currency);
+            bounty +=
+                Self::withdraw_pool_from_blend(e, &blend_address, pool, b_tokens, pool_keeper)?;
+        }
+
+        let mut lottery_state = storage::read_lottery_state(e)?;
+        lottery_state.in_blender = false;
+        storage::write_lottery_state(e, &lottery_state);
+        Ok(bounty)
+    }
+
+    /// Withdraws the pool's whole position. The yield is the value of its
+    /// `b_tokens` at the reserve's current `b_rate` minus what was supplied,
+    /// so funds sent to the contract from elsewhere never count as yield.
+    /// The keeper bounty comes out of the yield before it is recorded, so
+    /// the round record holds what is left for the prize.
+    fn withdraw_pool_from_blend(
+        e: &Env,
+        blend_address: &Address,
+        mut pool: Pool,
+        b_tokens: i128,
+        keeper: Option<&Address>,
+    ) -> Result<i128, LotteryError> {
+        let token_address = pool.token.clone();
+        let blend_client = blend::Client::new(e, blend_address);
+        let b_rate = blend_client.get_reserve(&token_address).data.b_rate;
+        let amount = util::b_tokens_to_underlying(b_tokens, b_rate);
+
+        let withdraw_request = blend::Request {
+            address: token_address.clone(),
+            amount,
+            request_type: 1,
+        };
+
+        let token_transfer_context = ContractContext {
+            contract: token_address.clone(),
+            fn_name: Symbol::new(e, "transfer"),
+            args: vec![
+                e,
+                blend_address.clone().into_val(e),
+                e.current_contract_address().into_val(e),
+                amount.into_val(e),
+            ],
+        };
+
+        let token_transfer_invocation = SubContractInvocation {
+            context: token_transfer_context,
+            sub_invocations: vec![e],
+        };
+
+        e.authorize_as_current_contract(vec![
+            e,
+            InvokerContractAuthEntry::Contract(token_transfer_invocation),
+        ]);
+
+        blend_client.submit(
+            &e.current_contract_address(),
+            &e.current_contract_address(),
+            &e.current_contract_address(),
+            &vec![e, withdraw_request],
+        );
+
+        let yield_gained = amount - pool.sent_balance;
+        let fee = util::protocol_fee(e, yield_gained);
+        let bounty = keeper.map_or(0, |_| util::keeper_bounty(e, yield_gained - fee));
+        let prize_yield = yield_gained - fee - bounty;
+
+        pool.amount_of_yield += prize_yield;
+        pool.sent_balance = 0;
+        storage::write_pool(e, &pool);
+        util::update_pool_record(e, &token_address, |record| {
+            record.amount_of_yield = prize_yield
+        })?;
+
+        events::withdrawn_from_blend(e, &token_address, amount, yield_gained);
+        if fee > 0 {
+            let accrued_fees = storage::read_accrued_fees(e, &token_address);
+            storage::write_accrued_fees(e, &token_address, accrued_fees + fee);
+            events::fees_collected(e, &token_address, fee);
+        }
+        if let Some(keeper) = keeper.filter(|_| bounty > 0) {
+            let token_client = token::Client::new(e, &token_address);
+            token_client.transfer(&e.current_contract_address(), keeper, &bounty);
+        }
+        Ok(bounty)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use soroban_sdk::{
+        testutils::{Address as _, Events, Ledger},
+        token::{StellarAssetClient, TokenClient},
+        Val,
+    };
+
+    use super::*;
+
+    mod buy_tickets {
+        use super::*;
+
+        #[test]
+        fn status_buyin() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv {
+                user,
+                xlm_address,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            let ticket = lottery_client.buy_ticket(&user.clone());
+
+            let ticket_should_be = Ticket {
+                amount: 10_000_000,
+                id: 1,
+                user: user.clone(),
+                token: xlm_address,
+                won: false,
+                round_id: 1,
+                principal: 10_000_000,
+            };
+
+            let user_tickets = lottery_client.get_user_tickets(&user);
+
+            let user_tickets_should_be = vec![&e, ticket_should_be.clone()];
+
+            assert_eq!(user_tickets_should_be, user_tickets);
+            assert_eq!(ticket_should_be, ticket);
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #1)")]
+        fn status_yieldfarming() {
+            let e = Env::default();
+            e.mock_all_auths();
+            e.ledger().with_mut(|li| {
+                li.sequence_number = 1;
+                li.min_persistent_entry_ttl = 10_000_000;
+                li.min_temp_entry_ttl = 1_000_000;
+                li.max_entry_ttl = 1_000_001;
+            });
+
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            e.ledger().with_mut(|li| {
+                li.sequence_number = 100_000;
+            });
+
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+
+            lottery_client.buy_ticket(&user.clone());
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #1)")]
+        fn status_ended() {
+            let e = Env::default();
+            e.mock_all_auths();
+            e.ledger().with_mut(|li| {
+                li.sequence_number = 1;
+                li.min_persistent_entry_ttl = 10_000_000;
+                li.min_temp_entry_ttl = 1_000_000;
+                li.max_entry_ttl = 1_000_001;
+            });
+
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            e.ledger().with_mut(|li| {
+                li.sequence_number = 100_000;
+            });
+
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+
+            e.ledger().with_mut(|li| {
+                li.sequence_number = 300_000;
+            });
+
+            lottery_client.set_status(&LotteryStatus::Ended);
+
+            lottery_client.buy_ticket(&user.clone());
+        }
+
+        #[test]
+        fn buy_multiple() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv {
+                user,
+                xlm_address,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            let ticket = lottery_client.buy_ticket(&user.clone());
+            let ticket2 = lottery_client.buy_ticket(&user.clone());
+
+            let ticket_should_be = Ticket {
+                amount: 10_000_000,
+                id: 1,
+                user: user.clone(),
+                token: xlm_address.clone(),
+                won: false,
+                round_id: 1,
+                principal: 10_000_000,
+            };
+            let ticket2_should_be = Ticket {
+                amount: 10_000_000,
+                id: 2,
+                user: user.clone(),
+                token: xlm_address,
+                won: false,
+                round_id: 1,
+                principal: 10_000_000,
+            };
+
+            let user_tickets = lottery_client.get_user_tickets(&user);
+
+            let user_tickets_should_be =
+                vec![&e, ticket_should_be.clone(), ticket2_should_be.clone()];
+
+            assert_eq!(user_tickets_should_be, user_tickets);
+            assert_eq!(ticket_should_be, ticket);
+            assert_eq!(ticket2_should_be, ticket2);
+        }
+    }
+
+    mod deposits {
+        use super::*;
+
+        #[test]
+        fn opens_position() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv {
+                user,
+                xlm_token_client,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            let ticket = lottery_client.deposit(&user, &15_000_000);
+
+            assert_eq!(15_000_000, ticket.amount);
+            assert_eq!(15_000_000, ticket.principal);
+            assert_eq!(5_000_000, xlm_token_client.balance(&user));
+            assert_eq!(1, lottery_client.balance(&user));
+            assert_eq!(1, lottery_client.get_lottery_state().no_participants);
+        }
+
+        #[test]
+        fn tops_up_position() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            let first = lottery_client.deposit(&user, &5_000_000);
+            let second = lottery_client.deposit(&user, &7_000_000);
+
+            assert_eq!(first.id, second.id);
+            assert_eq!(12_000_000, second.principal);
+            assert_eq!(vec![&e, second], lottery_client.get_user_tickets(&user));
+            assert_eq!(1, lottery_client.get_lottery_state().no_participants);
+        }
+
+        #[test]
+        fn transferred_position_not_topped_up() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+            let other = Address::generate(&e);
+
+            let first = lottery_client.deposit(&user, &5_000_000);
+            lottery_client.transfer(&user, &other, &first.id);
+            let second = lottery_client.deposit(&user, &5_000_000);
+
+            assert_ne!(first.id, second.id);
+            assert_eq!(
+                5_000_000,
+                lottery_client.get_ticket_metadata(&first.id).principal
+            );
+        }
+
+        #[test]
+        fn redeem_pays_full_position() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv {
+                user,
+                xlm_token_client,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            lottery_client.deposit(&user, &5_000_000);
+            let ticket = lottery_client.deposit(&user, &7_000_000);
+            lottery_client.redeem_ticket_by_id(&user, &ticket.id);
+
+            assert_eq!(20_000_000, xlm_token_client.balance(&user));
+            assert_eq!(0, lottery_client.get_lottery_state().no_participants);
+
+            // Redeeming closes the position
+            let ticket = lottery_client.deposit(&user, &1_000_000);
+            assert_eq!(1_000_000, ticket.principal);
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #32)")]
+        fn zero_amount() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            lottery_client.deposit(&user, &0);
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #1)")]
+        fn status_yieldfarming() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            set_ledger(&e, 17_301);
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            lottery_client.deposit(&user, &5_000_000);
+        }
+
+        // The minnow holds 1 in 1 001 of the stake, so it should lose every
+        // one of these draws
+        fn whale_wins(sequence_number: u32) -> bool {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, sequence_number);
+            let TestEnv {
+                user,
+                xlm_asset_client,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            let whale = Address::generate(&e);
+            xlm_asset_client.mint(&whale, &10_000_000_000_i128);
+            lottery_client.buy_ticket(&user);
+            lottery_client.deposit(&whale, &10_000_000_000);
+
+            set_ledger(&e, sequence_number + 17_300);
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            set_ledger(&e, sequence_number + 121_100);
+            lottery_client.set_status(&LotteryStatus::Ended);
+            lottery_client.raffle().get_unchecked(0).user == whale
+        }
+
+        #[test]
+        fn odds_weighted_by_stake() {
+            let mut whale_wins_count = 0;
+            for i in 0..8 {
+                if whale_wins(1 + i * 1_000) {
+                    whale_wins_count += 1;
+                }
+            }
+
+            assert!(whale_wins_count >= 7);
+        }
+    }
+
+    mod rolling_mode {
+        use super::*;
+
+        fn blend_supply(test_env: &TestEnv) -> i128 {
+            let b_tokens = test_env
+                .blend_client
+                .get_positions(&test_env.lottery_client.address)
+                .supply
+                .get(0)
+                .unwrap_or(0);
+            let b_rate = test_env
+                .blend_client
+                .get_reserve(&test_env.xlm_address)
+                .data
+                .b_rate;
+            util::b_tokens_to_underlying(b_tokens, b_rate)
+        }
+
+        #[test]
+        fn off_by_default() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+
+            assert!(!lottery_client.get_rolling_mode());
+        }
+
+        #[test]
+        fn deposit_while_farming_goes_to_blend() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let test_env = setup_test_env(&e);
+            let TestEnv {
+                admin,
+                user,
+                xlm_token_client,
+                lottery_client,
+                ..
+            } = &test_env;
+            lottery_client.set_rolling_mode(&true);
+
+            lottery_client.buy_ticket(user);
+            set_ledger(&e, 17_301);
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            lottery_client.blend_it(admin);
+
+            lottery_client.deposit(user, &5_000_000);
+
+            assert_eq!(15_000_000, blend_supply(&test_env));
+            assert_eq!(0, xlm_token_client.balance(&lottery_client.address));
+            assert_eq!(
+                15_000_000,
+                lottery_client
+                    .get_current_round()
+                    .pools
+                    .get_unchecked(0)
+                    .total_deposited
+            );
+        }
+
+        #[test]
+        fn redeem_while_farming_leaves_yield() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let test_env = setup_test_env(&e);
+            let TestEnv {
+                user,
+                xlm_asset_client,
+                xlm_token_client,
+                xlm_address,
+                lottery_client,
+                ..
+            } = &test_env;
+            let other = Address::generate(&e);
+            xlm_asset_client.mint(&other, &20_000_000_i128);
+            lottery_client.set_rolling_mode(&true);
+
+            let ticket = lottery_client.buy_ticket(user);
+            lottery_client.buy_ticket(&other);
+            start_farming(&e, &test_env, 17_301);
+            accrue_yield(&test_env, 1_000);
+
+            lottery_client.redeem_ticket_by_id(user, &ticket.id);
+            assert_eq!(20_000_000, xlm_token_client.balance(user));
+            // Blend rounds the burned b-tokens up, so the redemption costs
+            // the remaining position a stroop
+            assert_eq!(10_000_999, blend_supply(&test_env));
+            assert_eq!(1, lottery_client.get_lottery_state().no_participants);
+
+            end_farming(&e, &test_env, 121_101);
+            assert_eq!(999, lottery_client.get_pool(xlm_address).amount_of_yield);
+        }
+
+        fn end_farmed_round(e: &Env, test_env: &TestEnv) {
+            test_env.lottery_client.set_rolling_mode(&true);
+            test_env.lottery_client.buy_ticket(&test_env.user);
+            start_farming(e, test_env, 17_301);
+            end_farming(e, test_env, 121_101);
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #1)")]
+        fn deposits_closed_until_draw() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let test_env = setup_test_env(&e);
+
+            end_farmed_round(&e, &test_env);
+            test_env.lottery_client.deposit(&test_env.user, &5_000_000);
+        }
+
+        #[test]
+        fn deposits_reopen_after_draw() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let test_env = setup_test_env(&e);
+
+            end_farmed_round(&e, &test_env);
+            test_env.lottery_client.raffle();
+            let ticket = test_env.lottery_client.deposit(&test_env.user, &5_000_000);
+
+            assert_eq!(5_000_000, ticket.principal);
+            assert_eq!(
+                0,
+                test_env.lottery_client.get_ticket_odds(&ticket.id).weight
+            );
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #1)")]
+        fn set_during_yieldfarming() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+
+            set_ledger(&e, 17_301);
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            lottery_client.set_rolling_mode(&true);
+        }
+
+        #[test]
+        fn late_deposit_does_not_stall_raffle() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let test_env = setup_test_env(&e);
+            let lottery_client = &test_env.lottery_client;
+            let keeper = Address::generate(&e);
+            lottery_client.grant_role(&Role::Keeper, &keeper);
+            lottery_client.set_rolling_mode(&true);
+            lottery_client.set_prize_tiers(&vec![
+                &e,
+                PrizeTier { share_bps: 5_000 },
+                PrizeTier { share_bps: 5_000 },
+            ]);
+
+            let ticket = lottery_client.buy_ticket(&test_env.user);
+            set_ledger(&e, 17_301);
+            assert_eq!(AdvanceStep::StartFarming, lottery_client.advance(&keeper));
+            assert_eq!(AdvanceStep::DepositToBlend, lottery_client.advance(&keeper));
+            accrue_yield(&test_env, 1_000);
+
+            // Deposited in the ledger farming ends, so it weighs nothing
+            set_ledger(&e, 121_101);
+            let other = Address::generate(&e);
+            test_env.xlm_asset_client.mint(&other, &20_000_000_i128);
+            lottery_client.deposit(&other, &10_000_000);
+            assert_eq!(AdvanceStep::EndFarming, lottery_client.advance(&keeper));
+            assert_eq!(
+                AdvanceStep::WithdrawFromBlend,
+                lottery_client.advance(&keeper)
+            );
+            assert_eq!(AdvanceStep::Raffle, lottery_client.advance(&keeper));
+
+            let tickets = lottery_client.get_user_tickets(&test_env.user);
+            assert_eq!(ticket.id, tickets.get_unchecked(0).id);
+            assert_eq!(10_001_000, tickets.get_unchecked(0).amount);
+            assert_eq!(
+                10_000_000,
+                lottery_client
+                    .get_user_tickets(&other)
+                    .get_unchecked(0)
+                    .amount
+            );
+        }
+
+        #[test]
+        fn weightless_pool_is_not_drawn() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let test_env = setup_test_env(&e);
+            let lottery_client = &test_env.lottery_client;
+            let keeper = Address::generate(&e);
+            lottery_client.grant_role(&Role::Keeper, &keeper);
+            lottery_client.set_rolling_mode(&true);
+
+            set_ledger(&e, 17_301);
+            assert_eq!(AdvanceStep::StartFarming, lottery_client.advance(&keeper));
+            set_ledger(&e, 121_101);
+            lottery_client.buy_ticket(&test_env.user);
+            assert_eq!(AdvanceStep::DepositToBlend, lottery_client.advance(&keeper));
+            assert_eq!(AdvanceStep::EndFarming, lottery_client.advance(&keeper));
+            assert_eq!(
+                AdvanceStep::WithdrawFromBlend,
+                lottery_client.advance(&keeper)
+            );
+
+            set_ledger(&e, 138_401);
+            assert_eq!(AdvanceStep::StartBuyIn, lottery_client.advance(&keeper));
+        }
+    }
+
+    mod redeem_tickets {
+        use super::*;
+
+        #[test]
+        fn status_buyin() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            let ticket = lottery_client.buy_ticket(&user.clone());
+            lottery_client.redeem_ticket_by_id(&user, &ticket.id);
+
+            let user_tickets = lottery_client.get_user_tickets(&user);
+
+            let user_tickets_should_be = vec![&e];
+
+            assert_eq!(user_tickets_should_be, user_tickets);
+            //TODO: Maybe check the Ids vec?
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #1)")]
+        fn status_yieldfarming() {
+            let e = Env::default();
+            e.mock_all_auths();
+            e.ledger().with_mut(|li| {
+                li.sequence_number = 1;
+                li.min_persistent_entry_ttl = 10_000_000;
+                li.min_temp_entry_ttl = 1_000_000;
+                li.max_entry_ttl = 1_000_001;
+            });
+
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            let ticket = lottery_client.buy_ticket(&user.clone());
+
+            e.ledger().with_mut(|li| {
+                li.sequence_number = 100_000;
+            });
+
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            lottery_client.redeem_ticket_by_id(&user, &ticket.id);
+        }
+
+        #[test]
+        fn status_ended() {
+            let e = Env::default();
+            e.mock_all_auths();
+            e.ledger().with_mut(|li| {
+                li.sequence_number = 1;
+                li.min_persistent_entry_ttl = 10_000_000;
+                li.min_temp_entry_ttl = 1_000_000;
+                li.max_entry_ttl = 1_000_001;
+            });
+
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            let ticket = lottery_client.buy_ticket(&user.clone());
+            e.ledger().with_mut(|li| {
+                li.sequence_number = 100_000;
+            });
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            e.ledger().with_mut(|li| {
+                li.sequence_number = 300_000;
+            });
+            lottery_client.set_status(&LotteryStatus::Ended);
+            lottery_client.redeem_ticket_by_id(&user, &ticket.id);
+
+            let user_tickets = lottery_client.get_user_tickets(&user);
+
+            let user_tickets_should_be = vec![&e];
+
+            assert_eq!(user_tickets_should_be, user_tickets);
+        }
+
+        #[test]
+        fn redeem_multiple() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            let ticket = lottery_client.buy_ticket(&user.clone());
+            let ticket2 = lottery_client.buy_ticket(&user.clone());
+
+            lottery_client.redeem_ticket_by_id(&user, &ticket.id);
+            lottery_client.redeem_ticket_by_id(&user, &ticket2.id);
+
+            let user_tickets = lottery_client.get_user_tickets(&user);
+
+            let user_tickets_should_be = vec![&e];
+
+            assert_eq!(user_tickets_should_be, user_tickets);
+        }
+
+        #[test]
+        fn pays_stored_amount() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv {
+                user,
+                xlm_asset_client,
+                xlm_token_client,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            let other_user = Address::generate(&e);
+            xlm_asset_client.mint(&other_user, &20_000_000_i128);
+
+            let ticket = lottery_client.buy_ticket(&user);
+            lottery_client.buy_ticket(&other_user);
+            lottery_client.buy_ticket(&other_user);
+
+            lottery_client.redeem_ticket_by_id(&user, &ticket.id);
+
+            assert_eq!(20_000_000, xlm_token_client.balance(&user));
+            assert_eq!(
+                20_000_000,
+                xlm_token_client.balance(&lottery_client.address)
+            );
+        }
+
+        #[test]
+        fn pays_stored_token() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv {
+                admin,
+                user,
+                xlm_token_client,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            let forged_address = e
+                .register_stellar_asset_contract_v2(admin.clone())
+                .address();
+            let forged_asset_client = StellarAssetClient::new(&e, &forged_address);
+            let forged_token_client = TokenClient::new(&e, &forged_address);
+            forged_asset_client.mint(&lottery_client.address, &50_000_000_i128);
+
+            let ticket = lottery_client.buy_ticket(&user);
+            lottery_client.redeem_ticket_by_id(&user, &ticket.id);
+
+            assert_eq!(20_000_000, xlm_token_client.balance(&user));
+            assert_eq!(0, forged_token_client.balance(&user));
+            assert_eq!(
+                50_000_000,
+                forged_token_client.balance(&lottery_client.address)
+            );
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #8)")]
+        fn owned_by_someone_else() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            let attacker = Address::generate(&e);
+            let ticket = lottery_client.buy_ticket(&user);
+
+            lottery_client.redeem_ticket_by_id(&attacker, &ticket.id);
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #6)")]
+        fn unknown_id() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            lottery_client.buy_ticket(&user);
+            lottery_client.redeem_ticket_by_id(&user, &42);
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #6)")]
+        fn redeem_twice() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            let ticket = lottery_client.buy_ticket(&user);
+            lottery_client.buy_ticket(&user);
+
+            lottery_client.redeem_ticket_by_id(&user, &ticket.id);
+            lottery_client.redeem_ticket_by_id(&user, &ticket.id);
+        }
+
+        //TODO: redeem_user_won?
+    }
+
+    mod ticket_nft {
+        use super::*;
+
+        #[test]
+        fn buy_mints_ticket() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            let ticket = lottery_client.buy_ticket(&user);
+
+            assert_eq!(user, lottery_client.owner_of(&ticket.id));
+            assert_eq!(1, lottery_client.balance(&user));
+            assert_eq!(1, lottery_client.total_supply());
+            assert_eq!(ticket.id, lottery_client.get_owner_token_id(&user, &0));
+            assert_eq!(
+                String::from_str(&e, storage::TICKET_SYMBOL),
+                lottery_client.symbol()
+            );
+        }
+
+        #[test]
+        fn transfer_moves_ownership() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+            let buyer = Address::generate(&e);
+
+            let ticket = lottery_client.buy_ticket(&user);
+            lottery_client.buy_ticket(&user);
+            lottery_client.transfer(&user, &buyer, &ticket.id);
+
+            assert_eq!(buyer, lottery_client.owner_of(&ticket.id));
+            assert_eq!(1, lottery_client.get_user_tickets(&user).len());
+            let buyer_tickets = lottery_client.get_user_tickets(&buyer);
+            assert_eq!(ticket.id, buyer_tickets.get_unchecked(0).id);
+            assert_eq!(buyer, buyer_tickets.get_unchecked(0).user);
+            assert_eq!(2, lottery_client.get_lottery_state().no_participants);
+
+            lottery_client.redeem_ticket_by_id(&buyer, &ticket.id);
+            assert_eq!(1, lottery_client.get_lottery_state().no_participants);
+        }
+
+        #[test]
+        fn transfer_from_approved() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+            let marketplace = Address::generate(&e);
+            let buyer = Address::generate(&e);
+
+            let ticket = lottery_client.buy_ticket(&user);
+            lottery_client.approve(&user, &marketplace, &ticket.id, &1_000);
+            lottery_client.transfer_from(&marketplace, &user, &buyer, &ticket.id);
+
+            assert_eq!(buyer, lottery_client.owner_of(&ticket.id));
+            assert_eq!(1, lottery_client.get_lottery_state().no_participants);
+        }
+
+        #[test]
+        fn holder_receives_prize() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let test_env = setup_test_env(&e);
+            let buyer = Address::generate(&e);
+
+            let ticket = test_env.lottery_client.buy_ticket(&test_env.user);
+
+            farm_round(&e, &test_env, 1, 1_000);
+            let winners = test_env.lottery_client.raffle();
+            assert_eq!(test_env.user, winners.get_unchecked(0).user);
+
+            test_env
+                .lottery_client
+                .transfer(&test_env.user, &buyer, &ticket.id);
+            test_env
+                .lottery_client
+                .redeem_ticket_by_id(&buyer, &ticket.id);
+
+            assert_eq!(10_001_000, test_env.xlm_token_client.balance(&buyer));
+            assert_eq!(0, test_env.lottery_client.total_supply());
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #8)")]
+        fn previous_holder_cannot_redeem() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+            let buyer = Address::generate(&e);
+
+            let ticket = lottery_client.buy_ticket(&user);
+            lottery_client.transfer(&user, &buyer, &ticket.id);
+            lottery_client.redeem_ticket_by_id(&user, &ticket.id);
+        }
+
+        #[test]
+        fn metadata() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let test_env = setup_test_env(&e);
+
+            let ticket = test_env.lottery_client.buy_ticket(&test_env.user);
+            assert_eq!(
+                TicketMetadata {
+                    round_id: 1,
+                    principal: 10_000_000,
+                    won: false,
+                },
+                test_env.lottery_client.get_ticket_metadata(&ticket.id)
+            );
+
+            farm_round(&e, &test_env, 1, 1_000);
+            test_env.lottery_client.raffle();
+
+            let metadata = test_env.lottery_client.get_ticket_metadata(&ticket.id);
+            assert_eq!(10_000_000, metadata.principal);
+            assert!(metadata.won);
+        }
+    }
+
+    mod pause {
+        use super::*;
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #1000)")]
+        fn blocks_buy_ticket() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv {
+                admin,
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            lottery_client.pause(&admin);
+            lottery_client.buy_ticket(&user);
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #1000)")]
+        fn blocks_set_status() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv {
+                admin,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            lottery_client.pause(&admin);
+            set_ledger(&e, 17_301);
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #1000)")]
+        fn blocks_blend_it() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv {
+                admin,
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            lottery_client.buy_ticket(&user);
+            set_ledger(&e, 17_301);
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            lottery_client.pause(&admin);
+            lottery_client.blend_it(&admin);
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #1000)")]
+        fn blocks_raffle() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv {
+                admin,
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            lottery_client.buy_ticket(&user);
+            set_ledger(&e, 17_301);
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            lottery_client.blend_it(&admin);
+            set_ledger(&e, 121_101);
+            lottery_client.set_status(&LotteryStatus::Ended);
+            lottery_client.withdraw_from_blend(&admin);
+            lottery_client.pause(&admin);
+            lottery_client.raffle();
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #1000)")]
+        fn blocks_advance() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv {
+                admin,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            lottery_client.pause(&admin);
+            set_ledger(&e, 17_301);
+            lottery_client.advance(&admin);
+        }
+
+        #[test]
+        fn emergency_withdraw_and_redeem() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv {
+                admin,
+                user,
+                xlm_token_client,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            let ticket = lottery_client.buy_ticket(&user);
+            set_ledger(&e, 17_301);
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            lottery_client.blend_it(&admin);
+
+            lottery_client.pause(&admin);
+            assert!(lottery_client.paused());
+            lottery_client.withdraw_from_blend(&admin);
+            assert!(!lottery_client.get_lottery_state().in_blender);
+
+            lottery_client.redeem_ticket_by_id(&user, &ticket.id);
+            assert_eq!(20_000_000, xlm_token_client.balance(&user));
+            assert_eq!(
+                LotteryStatus::YieldFarming,
+                lottery_client.get_lottery_state().status
+            );
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #1)")]
+        fn redeem_while_in_blender() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv {
+                admin,
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            let ticket = lottery_client.buy_ticket(&user);
+            set_ledger(&e, 17_301);
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            lottery_client.blend_it(&admin);
+
+            lottery_client.pause(&admin);
+            lottery_client.redeem_ticket_by_id(&user, &ticket.id);
+        }
+
+        #[test]
+        fn unpause() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv {
+                admin,
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            lottery_client.pause(&admin);
+            lottery_client.unpause(&admin);
+
+            assert!(!lottery_client.paused());
+            lottery_client.buy_ticket(&user);
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #8)")]
+        fn pause_not_admin() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            lottery_client.pause(&user);
+        }
+    }
+
+    mod set_status {
+        use super::*;
+
+        #[test]
+        fn set_status_yieldfarming() {
+            let e = Env::default();
+            e.mock_all_auths();
+            e.ledger().with_mut(|li| {
+                li.sequence_number = 1;
+                li.min_persistent_entry_ttl = 10_000_000;
+                li.min_temp_entry_ttl = 1_000_000;
+                li.max_entry_ttl = 1_000_001;
+            });
+
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+
+            e.ledger().with_mut(|li| {
+                li.sequence_number = 100_000;
+            });
+
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+
+            let state = lottery_client.get_lottery_state();
+
+            assert_eq!(LotteryStatus::YieldFarming, state.status);
+        }
+
+        #[test]
+        fn set_status_ended() {
+            let e = Env::default();
+            e.mock_all_auths();
+            e.ledger().with_mut(|li| {
+                li.sequence_number = 1;
+                li.min_persistent_entry_ttl = 10_000_000;
+                li.min_temp_entry_ttl = 1_000_000;
+                li.max_entry_ttl = 1_000_001;
+            });
+
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+
+            e.ledger().with_mut(|li| {
+                li.sequence_number = 100_000;
+            });
+
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            e.ledger().with_mut(|li| {
+                li.sequence_number = 300_000;
+            });
+            lottery_client.set_status(&LotteryStatus::Ended);
+
+            let state = lottery_client.get_lottery_state();
+
+            assert_eq!(LotteryStatus::Ended, state.status);
+        }
+
+        #[test]
+        fn set_status_buyin() {
+            let e = Env::default();
+            e.mock_all_auths();
+            e.ledger().with_mut(|li| {
+                li.sequence_number = 1;
+                li.min_persistent_entry_ttl = 10_000_000;
+                li.min_temp_entry_ttl = 1_000_000;
+                li.max_entry_ttl = 1_000_001;
+            });
+
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+            e.ledger().with_mut(|li| {
+                li.sequence_number = 100_000;
+            });
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            e.ledger().with_mut(|li| {
+                li.sequence_number = 300_000;
+            });
+            lottery_client.set_status(&LotteryStatus::Ended);
+            e.ledger().with_mut(|li| {
+                li.sequence_number = 400_000;
+            });
+            lottery_client.set_status(&LotteryStatus::BuyIn);
+
+            let state = lottery_client.get_lottery_state();
+
+            assert_eq!(LotteryStatus::BuyIn, state.status);
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #1)")]
+        fn set_status_ended_after_buyin() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+            lottery_client.set_status(&LotteryStatus::Ended);
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #1)")]
+        fn set_status_yieldfarming_after_ended() {
+            let e = Env::default();
+            e.mock_all_auths();
+            e.ledger().with_mut(|li| {
+                li.sequence_number = 1;
+                li.min_persistent_entry_ttl = 10_000_000;
+                li.min_temp_entry_ttl = 1_000_000;
+                li.max_entry_ttl = 1_000_001;
+            });
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+            e.ledger().with_mut(|li| {
+                li.sequence_number = 100_000;
+            });
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            e.ledger().with_mut(|li| {
+                li.sequence_number = 300_000;
+            });
+            lottery_client.set_status(&LotteryStatus::Ended);
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #1)")]
+        fn set_status_buyin_after_yieldfarming() {
+            let e = Env::default();
+            e.mock_all_auths();
+            e.ledger().with_mut(|li| {
+                li.sequence_number = 1;
+                li.min_persistent_entry_ttl = 10_000_000;
+                li.min_temp_entry_ttl = 1_000_000;
+                li.max_entry_ttl = 1_000_001;
+            });
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+            e.ledger().with_mut(|li| {
+                li.sequence_number = 100_000;
+            });
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            lottery_client.set_status(&LotteryStatus::BuyIn);
+        }
+    }
+
+    mod raffle {
+        use super::*;
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #1)")]
+        fn status_buyin() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+
+            lottery_client.raffle();
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #1)")]
+        fn status_yieldfarming() {
+            let e = Env::default();
+            e.mock_all_auths();
+            e.ledger().with_mut(|li| {
+                li.sequence_number = 1;
+                li.min_persistent_entry_ttl = 10_000_000;
+                li.min_temp_entry_ttl = 1_000_000;
+                li.max_entry_ttl = 1_000_001;
+            });
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+            e.ledger().with_mut(|li| {
+                li.sequence_number = 100_000;
+            });
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            lottery_client.raffle();
+        }
+
+        #[test]
+        fn status_ended() {
+            let e = Env::default();
+            e.mock_all_auths();
+            e.ledger().with_mut(|li| {
+                li.sequence_number = 1;
+                li.min_persistent_entry_ttl = 10_000_000;
+                li.min_temp_entry_ttl = 1_000_000;
+                li.max_entry_ttl = 1_000_001;
+            });
+            let TestEnv {
+                lottery_client,
+                user,
+                ..
+            } = setup_test_env(&e);
+
+            let mut user_ticket = lottery_client.buy_ticket(&user);
+
+            e.ledger().with_mut(|li| {
+                li.sequence_number = 100_000;
+            });
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            e.ledger().with_mut(|li| {
+                li.sequence_number = 300_000;
+            });
+            lottery_client.set_status(&LotteryStatus::Ended);
+            let winners = lottery_client.raffle();
+
+            user_ticket.won = true;
+
+            assert_eq!(vec![&e, user_ticket], winners)
+        }
+    }
+
+    mod empty_round {
+        use super::*;
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #30)")]
+        fn no_ticket_ever_bought() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+
+            set_ledger(&e, 100_000);
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            set_ledger(&e, 300_000);
+            lottery_client.set_status(&LotteryStatus::Ended);
+            lottery_client.raffle();
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #30)")]
+        fn all_tickets_redeemed() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            let ticket = lottery_client.buy_ticket(&user);
+            lottery_client.redeem_ticket_by_id(&user, &ticket.id);
+
+            set_ledger(&e, 100_000);
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            set_ledger(&e, 300_000);
+            lottery_client.set_status(&LotteryStatus::Ended);
+            lottery_client.raffle();
+        }
+
+        #[test]
+        fn back_to_buyin() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+
+            set_ledger(&e, 100_000);
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            set_ledger(&e, 300_000);
+            lottery_client.set_status(&LotteryStatus::Ended);
+            set_ledger(&e, 400_000);
+            lottery_client.set_status(&LotteryStatus::BuyIn);
+
+            let state = lottery_client.get_lottery_state();
+
+            assert_eq!(LotteryStatus::BuyIn, state.status);
+            assert_eq!(2, lottery_client.get_current_round().round_id);
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #16)")]
+        fn back_to_buyin_while_in_blender() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv {
+                admin,
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            lottery_client.buy_ticket(&user);
+            set_ledger(&e, 100_000);
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            lottery_client.blend_it(&admin);
+            set_ledger(&e, 300_000);
+            lottery_client.set_status(&LotteryStatus::Ended);
+            set_ledger(&e, 400_000);
+            lottery_client.set_status(&LotteryStatus::BuyIn);
+        }
+
+        #[test]
+        fn yield_rolls_over() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let test_env = setup_test_env(&e);
+            let TestEnv {
+                user,
+                xlm_address,
+                lottery_client,
+                ..
+            } = &test_env;
+
+            // Round 1: the only ticket is redeemed before the raffle
+            let ticket = lottery_client.buy_ticket(user);
+            farm_round(&e, &test_env, 1, 1_000);
+            lottery_client.redeem_ticket_by_id(user, &ticket.id);
+            assert!(lottery_client.try_raffle().is_err());
+            set_ledger(&e, 138_401);
+            lottery_client.set_status(&LotteryStatus::BuyIn);
+
+            assert_eq!(0, lottery_client.get_pool(xlm_address).amount_of_yield);
+            assert_eq!(1_000, lottery_client.get_jackpot(xlm_address));
+
+            // Round 2: the rolled over prize is added to the new yield
+            let ticket = lottery_client.buy_ticket(user);
+            farm_round(&e, &test_env, 138_401, 500);
+            let winners = lottery_client.raffle();
+
+            assert_eq!(ticket.id, winners.get_unchecked(0).id);
+            assert_eq!(10_001_500, winners.get_unchecked(0).amount);
+            assert_eq!(0, lottery_client.get_pool(xlm_address).amount_of_yield);
+            assert_eq!(0, lottery_client.get_jackpot(xlm_address));
+        }
+    }
+
+    mod phase_durations {
+        use super::*;
+
+        fn short_phase_durations() -> PhaseDurations {
+            PhaseDurations {
+                buyin_ledgers: 100,
+                farming_ledgers: 700,
+                ended_ledgers: 100,
+                reveal_ledgers: 50,
+            }
+        }
+
+        #[test]
+        fn constructor_durations() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv { lottery_client, .. } =
+                setup_test_env_with_durations(&e, short_phase_durations());
+
+            set_ledger(&e, 101);
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            set_ledger(&e, 801);
+            lottery_client.set_status(&LotteryStatus::Ended);
+            set_ledger(&e, 901);
+            lottery_client.set_status(&LotteryStatus::BuyIn);
+
+            assert_eq!(
+                short_phase_durations(),
+                lottery_client.get_phase_durations()
+            );
+            assert_eq!(
+                LotteryStatus::BuyIn,
+                lottery_client.get_lottery_state().status
+            );
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #21)")]
+        fn constructor_durations_timelock() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv { lottery_client, .. } =
+                setup_test_env_with_durations(&e, short_phase_durations());
+
+            set_ledger(&e, 100);
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #34)")]
+        fn constructor_reveal_longer_than_ended() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let mut durations = short_phase_durations();
+            durations.reveal_ledgers = 101;
+
+            setup_test_env_with_durations(&e, durations);
+        }
+
+        #[test]
+        fn proposal_applies_from_next_round() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+
+            let pending = lottery_client.propose_phase_durations(&short_phase_durations());
+            assert_eq!(138_401, pending.unlock_ledger);
+
+            // The current round keeps the old durations
+            set_ledger(&e, 17_301);
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            set_ledger(&e, 121_101);
+            lottery_client.set_status(&LotteryStatus::Ended);
+            assert_eq!(
+                default_phase_durations(),
+                lottery_client.get_phase_durations()
+            );
+
+            set_ledger(&e, 138_401);
+            lottery_client.set_status(&LotteryStatus::BuyIn);
+
+            assert_eq!(
+                short_phase_durations(),
+                lottery_client.get_phase_durations()
+            );
+            assert_eq!(None, lottery_client.get_pending_phase_durations());
+
+            set_ledger(&e, 138_501);
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+        }
+
+        #[test]
+        fn proposal_waits_for_timelock() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+
+            set_ledger(&e, 10_000);
+            let pending = lottery_client.propose_phase_durations(&short_phase_durations());
+
+            set_ledger(&e, 17_301);
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            set_ledger(&e, 121_101);
+            lottery_client.set_status(&LotteryStatus::Ended);
+            set_ledger(&e, 138_401);
+            lottery_client.set_status(&LotteryStatus::BuyIn);
+
+            assert_eq!(
+                default_phase_durations(),
+                lottery_client.get_phase_durations()
+            );
+            assert_eq!(Some(pending), lottery_client.get_pending_phase_durations());
+        }
+
+        #[test]
+        fn cancel_proposal() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+
+            lottery_client.propose_phase_durations(&short_phase_durations());
+            lottery_client.cancel_phase_durations();
+
+            set_ledger(&e, 17_301);
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            set_ledger(&e, 121_101);
+            lottery_client.set_status(&LotteryStatus::Ended);
+            set_ledger(&e, 138_401);
+            lottery_client.set_status(&LotteryStatus::BuyIn);
+
+            assert_eq!(
+                default_phase_durations(),
+                lottery_client.get_phase_durations()
+            );
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #34)")]
+        fn propose_zero_duration() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+
+            let mut durations = short_phase_durations();
+            durations.farming_ledgers = 0;
+            lottery_client.propose_phase_durations(&durations);
+        }
+    }
+
+    mod jackpot {
+        use super::*;
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #31)")]
+        fn below_min_prize() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let test_env = setup_test_env(&e);
+
+            test_env
+                .lottery_client
+                .set_min_prize(&test_env.xlm_address, &1_500);
+            test_env.lottery_client.buy_ticket(&test_env.user);
+            farm_round(&e, &test_env, 1, 1_000);
+            test_env.lottery_client.raffle();
+        }
+
+        #[test]
+        fn accumulates_until_min_prize() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let test_env = setup_test_env(&e);
+
+            test_env
+                .lottery_client
+                .set_min_prize(&test_env.xlm_address, &1_500);
+            let ticket = test_env.lottery_client.buy_ticket(&test_env.user);
+
+            farm_round(&e, &test_env, 1, 1_000);
+            assert_eq!(
+                1_000,
+                test_env.lottery_client.get_prize_pot(&test_env.xlm_address)
+            );
+            assert!(test_env.lottery_client.try_raffle().is_err());
+            set_ledger(&e, 150_001);
+            test_env.lottery_client.set_status(&LotteryStatus::BuyIn);
+
+            assert_eq!(
+                1_000,
+                test_env.lottery_client.get_jackpot(&test_env.xlm_address)
+            );
+            assert_eq!(
+                1_000,
+                test_env.lottery_client.get_prize_pot(&test_env.xlm_address)
+            );
+
+            farm_round(&e, &test_env, 150_001, 600);
+            assert_eq!(
+                1_600,
+                test_env.lottery_client.get_prize_pot(&test_env.xlm_address)
+            );
+            let winners = test_env.lottery_client.raffle();
+
+            assert_eq!(ticket.id, winners.get_unchecked(0).id);
+            assert_eq!(10_001_600, winners.get_unchecked(0).amount);
+            assert_eq!(
+                0,
+                test_env.lottery_client.get_jackpot(&test_env.xlm_address)
+            );
+            assert_eq!(
+                0,
+                test_env.lottery_client.get_prize_pot(&test_env.xlm_address)
+            );
+            assert_eq!(
+                1_600,
+                test_env
+                    .lottery_client
+                    .get_current_round()
+                    .pools
+                    .get_unchecked(0)
+                    .prize_pot
+            );
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #32)")]
+        fn negative_min_prize() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv {
+                xlm_address,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            lottery_client.set_min_prize(&xlm_address, &-1);
+        }
+    }
+
+    mod advance {
+        use super::*;
+
+        #[test]
+        fn full_round() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let test_env = setup_test_env(&e);
+            let keeper = Address::generate(&e);
+            test_env.lottery_client.grant_role(&Role::Keeper, &keeper);
+
+            test_env.lottery_client.set_keeper_bounty(&100);
+            let ticket = test_env.lottery_client.buy_ticket(&test_env.user);
+
+            set_ledger(&e, 17_301);
+            assert_eq!(
+                AdvanceStep::StartFarming,
+                test_env.lottery_client.advance(&keeper)
+            );
+            assert_eq!(
+                AdvanceStep::DepositToBlend,
+                test_env.lottery_client.advance(&keeper)
+            );
+            assert!(test_env.lottery_client.get_lottery_state().in_blender);
+
+            accrue_yield(&test_env, 1_000);
+
+            set_ledger(&e, 121_101);
+            assert_eq!(
+                AdvanceStep::EndFarming,
+                test_env.lottery_client.advance(&keeper)
+            );
+            assert_eq!(
+                AdvanceStep::WithdrawFromBlend,
+                test_env.lottery_client.advance(&keeper)
+            );
+            assert_eq!(100, test_env.xlm_token_client.balance(&keeper));
+
+            assert_eq!(
+                AdvanceStep::Raffle,
+                test_env.lottery_client.advance(&keeper)
+            );
+            let winner = test_env.lottery_client.get_user_tickets(&test_env.user);
+            assert_eq!(ticket.id, winner.get_unchecked(0).id);
+            assert_eq!(10_000_900, winner.get_unchecked(0).amount);
+
+            set_ledger(&e, 138_401);
+            assert_eq!(
+                AdvanceStep::StartBuyIn,
+                test_env.lottery_client.advance(&keeper)
+            );
+
+            assert_eq!(100, test_env.xlm_token_client.balance(&keeper));
+            assert_eq!(
+                LotteryStatus::BuyIn,
+                test_env.lottery_client.get_lottery_state().status
+            );
+            assert_eq!(
+                900,
+                test_env
+                    .lottery_client
+                    .get_round(&1)
+                    .pools
+                    .get_unchecked(0)
+                    .prize_pot
+            );
+            assert_eq!(2, test_env.lottery_client.get_current_round().round_id);
+        }
+
+        #[test]
+        fn empty_round() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+            let keeper = Address::generate(&e);
+            lottery_client.grant_role(&Role::Keeper, &keeper);
+
+            set_ledger(&e, 17_301);
+            assert_eq!(AdvanceStep::StartFarming, lottery_client.advance(&keeper));
+            set_ledger(&e, 121_101);
+            assert_eq!(AdvanceStep::EndFarming, lottery_client.advance(&keeper));
+            set_ledger(&e, 138_401);
+            assert_eq!(AdvanceStep::StartBuyIn, lottery_client.advance(&keeper));
+
+            assert_eq!(
+                0,
+                lottery_client
+                    .get_round(&1)
+                    .pools
+                    .get_unchecked(0)
+                    .total_deposited
+            );
+        }
+
+        #[test]
+        fn rolls_over_below_min_prize() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let test_env = setup_test_env(&e);
+            let keeper = Address::generate(&e);
+            test_env.lottery_client.grant_role(&Role::Keeper, &keeper);
+
+            test_env.lottery_client.set_keeper_bounty(&100);
+            test_env
+                .lottery_client
+                .set_min_prize(&test_env.xlm_address, &5_000);
+            test_env.lottery_client.buy_ticket(&test_env.user);
+
+            set_ledger(&e, 17_301);
+            test_env.lottery_client.advance(&keeper);
+            test_env.lottery_client.advance(&keeper);
+            accrue_yield(&test_env, 1_000);
+            set_ledger(&e, 121_101);
+            test_env.lottery_client.advance(&keeper);
+            test_env.lottery_client.advance(&keeper);
+
+            set_ledger(&e, 138_401);
+            assert_eq!(
+                AdvanceStep::StartBuyIn,
+                test_env.lottery_client.advance(&keeper)
+            );
+            assert_eq!(100, test_env.xlm_token_client.balance(&keeper));
+            assert_eq!(
+                900,
+                test_env.lottery_client.get_jackpot(&test_env.xlm_address)
+            );
+            assert!(test_env
+                .lottery_client
+                .get_round(&1)
+                .winning_ticket_ids
+                .is_empty());
+        }
+
+        #[test]
+        fn bounty_never_taken_from_jackpot() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let test_env = setup_test_env(&e);
+            let lottery_client = &test_env.lottery_client;
+            let keeper = Address::generate(&e);
+            lottery_client.grant_role(&Role::Keeper, &keeper);
+            lottery_client.set_keeper_bounty(&100);
+            lottery_client.set_min_prize(&test_env.xlm_address, &10_000);
+            e.as_contract(&lottery_client.address, || {
+                let mut pool = storage::read_pool(&e, &test_env.xlm_address).unwrap();
+                pool.jackpot = 5_000;
+                storage::write_pool(&e, &pool);
+            });
+            test_env
+                .xlm_asset_client
+                .mint(&lottery_client.address, &5_000);
+            lottery_client.buy_ticket(&test_env.user);
+
+            // A round without yield runs every step but the raffle
+            set_ledger(&e, 17_301);
+            lottery_client.advance(&keeper);
+            lottery_client.advance(&keeper);
+            set_ledger(&e, 121_101);
+            lottery_client.advance(&keeper);
+            lottery_client.advance(&keeper);
+            set_ledger(&e, 138_401);
+            assert_eq!(AdvanceStep::StartBuyIn, lottery_client.advance(&keeper));
+
+            assert_eq!(0, test_env.xlm_token_client.balance(&keeper));
+            assert_eq!(5_000, lottery_client.get_jackpot(&test_env.xlm_address));
+        }
+
+        #[test]
+        fn bounty_capped_by_prize_pot() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let test_env = setup_test_env(&e);
+            let keeper = Address::generate(&e);
+            test_env.lottery_client.grant_role(&Role::Keeper, &keeper);
+
+            test_env.lottery_client.set_keeper_bounty(&1_500);
+            test_env.lottery_client.buy_ticket(&test_env.user);
+
+            set_ledger(&e, 17_301);
+            test_env.lottery_client.advance(&keeper);
+            test_env.lottery_client.advance(&keeper);
+            accrue_yield(&test_env, 1_000);
+            set_ledger(&e, 121_101);
+            test_env.lottery_client.advance(&keeper);
+            test_env.lottery_client.advance(&keeper);
+
+            assert_eq!(1_000, test_env.xlm_token_client.balance(&keeper));
+            assert_eq!(
+                0,
+                test_env.lottery_client.get_prize_pot(&test_env.xlm_address)
+            );
+            assert_eq!(10_000_000, test_env.lottery_client.get_contract_balance());
+        }
+
+        #[test]
+        fn recorded_yield_excludes_bounty() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let test_env = setup_test_env(&e);
+            let keeper = Address::generate(&e);
+            test_env.lottery_client.grant_role(&Role::Keeper, &keeper);
+
+            test_env.lottery_client.set_keeper_bounty(&100);
+            let ticket = test_env.lottery_client.buy_ticket(&test_env.user);
+
+            set_ledger(&e, 17_301);
+            test_env.lottery_client.advance(&keeper);
+            test_env.lottery_client.advance(&keeper);
+            accrue_yield(&test_env, 1_000);
+            set_ledger(&e, 121_101);
+            test_env.lottery_client.advance(&keeper);
+            test_env.lottery_client.advance(&keeper);
+
+            let recorded = test_env
+                .lottery_client
+                .get_current_round()
+                .pools
+                .get_unchecked(0)
+                .amount_of_yield;
+            assert_eq!(900, recorded);
+
+            test_env.lottery_client.advance(&keeper);
+            let winner = test_env.lottery_client.get_user_tickets(&test_env.user);
+            assert_eq!(ticket.id, winner.get_unchecked(0).id);
+            assert_eq!(
+                recorded,
+                winner.get_unchecked(0).amount - test_env.lottery_client.get_ticket_amount()
+            );
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #21)")]
+        fn nothing_due() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv {
+                admin,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            set_ledger(&e, 17_300);
+            lottery_client.advance(&admin);
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #32)")]
+        fn negative_bounty() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+
+            lottery_client.set_keeper_bounty(&-1);
+        }
+    }
+
+    mod prize_tiers {
+        use super::*;
+
+        fn tiers(e: &Env, shares: &[u32]) -> soroban_sdk::Vec<PrizeTier> {
+            let mut tiers = soroban_sdk::Vec::new(e);
+            for share_bps in shares {
+                tiers.push_back(PrizeTier {
+                    share_bps: *share_bps,
+                });
+            }
+            tiers
+        }
+
+        fn run_round(e: &Env, test_env: &TestEnv, no_tickets: u32, yield_amount: i128) {
+            set_ledger(e, 1);
+            for _ in 0..no_tickets {
+                let user = Address::generate(e);
+                test_env.xlm_asset_client.mint(&user, &10_000_000_i128);
+                test_env.lottery_client.buy_ticket(&user);
+            }
+            farm_round(e, test_env, 1, yield_amount);
+        }
+
+        #[test]
+        fn default_single_winner() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let test_env = setup_test_env(&e);
+
+            run_round(&e, &test_env, 3, 1_000);
+            let winners = test_env.lottery_client.raffle();
+
+            assert_eq!(1, winners.len());
+            assert_eq!(10_001_000, winners.get_unchecked(0).amount);
+        }
+
+        #[test]
+        fn three_tiers() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let test_env = setup_test_env(&e);
+
+            test_env
+                .lottery_client
+                .set_prize_tiers(&tiers(&e, &[6_000, 2_500, 1_500]));
+            run_round(&e, &test_env, 5, 1_000);
+            let winners = test_env.lottery_client.raffle();
+
+            assert_eq!(3, winners.len());
+            assert_eq!(10_000_600, winners.get_unchecked(0).amount);
+            assert_eq!(10_000_250, winners.get_unchecked(1).amount);
+            assert_eq!(10_000_150, winners.get_unchecked(2).amount);
+
+            let first_id = winners.get_unchecked(0).id;
+            let second_id = winners.get_unchecked(1).id;
+            let third_id = winners.get_unchecked(2).id;
+            assert!(first_id != second_id && first_id != third_id && second_id != third_id);
+
+            for winner in winners.iter() {
+                assert!(winner.won);
+                let stored = test_env.lottery_client.get_user_tickets(&winner.user);
+                assert_eq!(vec![&e, winner], stored);
+            }
+            assert_eq!(
+                0,
+                test_env
+                    .lottery_client
+                    .get_pool(&test_env.xlm_address)
+                    .amount_of_yield
+            );
+        }
+
+        #[test]
+        fn remainder_to_first_tier() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let test_env = setup_test_env(&e);
+
+            test_env
+                .lottery_client
+                .set_prize_tiers(&tiers(&e, &[6_000, 2_500, 1_500]));
+            run_round(&e, &test_env, 5, 1_003);
+            let winners = test_env.lottery_client.raffle();
+
+            assert_eq!(10_000_603, winners.get_unchecked(0).amount);
+            assert_eq!(10_000_250, winners.get_unchecked(1).amount);
+            assert_eq!(10_000_150, winners.get_unchecked(2).amount);
+        }
+
+        #[test]
+        fn zero_weight_ticket_with_more_tiers() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let test_env = setup_test_env(&e);
+            let lottery_client = &test_env.lottery_client;
+            lottery_client.set_rolling_mode(&true);
+            lottery_client.set_prize_tiers(&tiers(&e, &[6_000, 2_500, 1_500]));
+
+            set_ledger(&e, 1);
+            let ticket = lottery_client.buy_ticket(&test_env.user);
+            start_farming(&e, &test_env, 17_301);
+            accrue_yield(&test_env, 1_000);
+
+            // Bought in the ledger farming ends, so it has held nothing
+            set_ledger(&e, 121_101);
+            let late = Address::generate(&e);
+            test_env.xlm_asset_client.mint(&late, &10_000_000_i128);
+            lottery_client.buy_ticket(&late);
+            lottery_client.set_status(&LotteryStatus::Ended);
+            lottery_client.withdraw_from_blend(&test_env.admin);
+            let winners = lottery_client.raffle();
+
+            assert_eq!(1, winners.len());
+            assert_eq!(ticket.id, winners.get_unchecked(0).id);
+            assert_eq!(10_001_000, winners.get_unchecked(0).amount);
+            assert_eq!(
+                1_000,
+                lottery_client
+                    .get_pool(&test_env.xlm_address)
+                    .unclaimed_prizes
+            );
+        }
+
+        #[test]
+        fn fewer_tickets_than_tiers() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let test_env = setup_test_env(&e);
+
+            test_env
+                .lottery_client
+                .set_prize_tiers(&tiers(&e, &[6_000, 2_500, 1_500]));
+            run_round(&e, &test_env, 2, 1_000);
+            let winners = test_env.lottery_client.raffle();
+
+            assert_eq!(2, winners.len());
+            assert_eq!(10_000_750, winners.get_unchecked(0).amount);
+            assert_eq!(10_000_250, winners.get_unchecked(1).amount);
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #28)")]
+        fn shares_not_full() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+
+            lottery_client.set_prize_tiers(&tiers(&e, &[6_000, 2_500]));
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #28)")]
+        fn zero_share() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+
+            lottery_client.set_prize_tiers(&tiers(&e, &[10_000, 0]));
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #28)")]
+        fn no_tiers() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+
+            lottery_client.set_prize_tiers(&tiers(&e, &[]));
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #1)")]
+        fn set_during_yieldfarming() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+
+            set_ledger(&e, 100_000);
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            lottery_client.set_prize_tiers(&tiers(&e, &[10_000]));
+        }
+    }
+
+    mod round_history {
+        use super::*;
+        use crate::storage::PoolRecord;
+
+        // Runs one full round starting at `start_ledger` with a single ticket
+        // and closes it by moving back to BuyIn.
+        fn run_round(e: &Env, test_env: &TestEnv, start_ledger: u32, yield_amount: i128) {
+            set_ledger(e, start_ledger);
+            test_env
+                .xlm_asset_client
+                .mint(&test_env.user, &10_000_000_i128);
+            test_env.lottery_client.buy_ticket(&test_env.user);
+            farm_round(e, test_env, start_ledger, yield_amount);
+            test_env.lottery_client.raffle();
+
+            set_ledger(e, start_ledger + 150_000);
+            test_env.lottery_client.set_status(&LotteryStatus::BuyIn);
+        }
+
+        #[test]
+        fn first_round_open() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 10);
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+
+            let round = lottery_client.get_current_round();
+
+            assert_eq!(1, round.round_id);
+            assert_eq!(10, round.buyin_start_ledger);
+            assert!(round.winning_ticket_ids.is_empty());
+        }
+
+        #[test]
+        fn round_archived_on_close() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let test_env = setup_test_env(&e);
+
+            run_round(&e, &test_env, 1, 1_000);
+            let round = test_env.lottery_client.get_round(&1);
+
+            assert_eq!(1, round.round_id);
+            assert_eq!(1, round.buyin_start_ledger);
+            assert_eq!(17_301, round.buyin_end_ledger);
+            assert_eq!(17_301, round.farming_start_ledger);
+            assert_eq!(121_101, round.farming_end_ledger);
+            assert_eq!(121_101, round.ended_start_ledger);
+            assert_eq!(150_001, round.ended_end_ledger);
+            assert_eq!(1, round.no_participants);
+            assert_eq!(1, round.no_tickets);
+            assert_eq!(
+                vec![
+                    &e,
+                    PoolRecord {
+                        token: test_env.xlm_address.clone(),
+                        total_deposited: 10_000_000,
+                        amount_of_yield: 1_000,
+                        prize_pot: 1_000,
+                    }
+                ],
+                round.pools
+            );
+            assert_eq!(vec![&e, 1_u32], round.winning_ticket_ids);
+            assert_eq!(32, round.seed.len());
+
+            let current_round = test_env.lottery_client.get_current_round();
+            assert_eq!(2, current_round.round_id);
+            assert_eq!(150_001, current_round.buyin_start_ledger);
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #29)")]
+        fn open_round_not_archived() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+
+            lottery_client.get_round(&1);
+        }
+
+        #[test]
+        fn list_rounds_paginated() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let test_env = setup_test_env(&e);
+
+            run_round(&e, &test_env, 1, 1_000);
+            run_round(&e, &test_env, 200_000, 2_000);
+            run_round(&e, &test_env, 400_000, 3_000);
+
+            let first_page = test_env.lottery_client.list_rounds(&1, &2);
+            let second_page = test_env.lottery_client.list_rounds(&3, &2);
+
+            assert_eq!(2, first_page.len());
+            assert_eq!(1, first_page.get_unchecked(0).round_id);
+            assert_eq!(2, first_page.get_unchecked(1).round_id);
+            assert_eq!(
+                2_000,
+                first_page
+                    .get_unchecked(1)
+                    .pools
+                    .get_unchecked(0)
+                    .amount_of_yield
+            );
+            assert_eq!(1, second_page.len());
+            assert_eq!(3, second_page.get_unchecked(0).round_id);
+            assert_eq!(
+                3_000,
+                second_page
+                    .get_unchecked(0)
+                    .pools
+                    .get_unchecked(0)
+                    .amount_of_yield
+            );
+            assert!(test_env.lottery_client.list_rounds(&4, &2).is_empty());
+        }
+    }
+
+    mod randomness {
+        use super::*;
+
+        fn commitment_for(e: &Env, secret: &BytesN<32>) -> BytesN<32> {
+            e.crypto().sha256(&Bytes::from(secret.clone())).into()
+        }
+
+        fn move_to_ended(e: &Env, lottery_client: &NoLossLotteryClient) {
+            set_ledger(e, 100_000);
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            set_ledger(e, 300_000);
+            lottery_client.set_status(&LotteryStatus::Ended);
+        }
+
+        #[test]
+        fn commit_and_reveal() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            let secret = BytesN::from_array(&e, &[7; 32]);
+            let ticket = lottery_client.buy_ticket(&user);
+            lottery_client.commit(&user, &commitment_for(&e, &secret));
+
+            move_to_ended(&e, &lottery_client);
+            lottery_client.reveal(&user, &secret);
+            let winners = lottery_client.raffle();
+
+            assert_eq!(ticket.id, winners.get_unchecked(0).id);
+        }
+
+        #[test]
+        fn keeper_commit() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+
+            let keeper = Address::generate(&e);
+            let secret = BytesN::from_array(&e, &[7; 32]);
+            lottery_client.grant_role(&Role::Keeper, &keeper);
+            lottery_client.commit(&keeper, &commitment_for(&e, &secret));
+
+            let commitment = lottery_client.get_commitment(&1, &keeper).unwrap();
+
+            assert_eq!(commitment_for(&e, &secret), commitment.hash);
+            assert!(!commitment.revealed);
+            assert_eq!(1, lottery_client.get_randomness(&1).commitments);
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #8)")]
+        fn commit_not_participant() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+
+            let outsider = Address::generate(&e);
+            let secret = BytesN::from_array(&e, &[7; 32]);
+            lottery_client.commit(&outsider, &commitment_for(&e, &secret));
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #8)")]
+        fn commit_removed_keeper() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+
+            let keeper = Address::generate(&e);
+            let secret = BytesN::from_array(&e, &[7; 32]);
+            lottery_client.grant_role(&Role::Keeper, &keeper);
+            lottery_client.revoke_role(&Role::Keeper, &keeper);
+            lottery_client.commit(&keeper, &commitment_for(&e, &secret));
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #22)")]
+        fn commit_twice() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            let secret = BytesN::from_array(&e, &[7; 32]);
+            lottery_client.buy_ticket(&user);
+            lottery_client.commit(&user, &commitment_for(&e, &secret));
+            lottery_client.commit(&user, &commitment_for(&e, &secret));
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #1)")]
+        fn commit_status_ended() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            let secret = BytesN::from_array(&e, &[7; 32]);
+            lottery_client.buy_ticket(&user);
+            move_to_ended(&e, &lottery_client);
+            lottery_client.commit(&user, &commitment_for(&e, &secret));
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #25)")]
+        fn reveal_wrong_secret() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            let secret = BytesN::from_array(&e, &[7; 32]);
+            lottery_client.buy_ticket(&user);
+            lottery_client.commit(&user, &commitment_for(&e, &secret));
+
+            move_to_ended(&e, &lottery_client);
+            lottery_client.reveal(&user, &BytesN::from_array(&e, &[8; 32]));
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #26)")]
+        fn raffle_waits_for_reveals() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            let secret = BytesN::from_array(&e, &[7; 32]);
+            lottery_client.buy_ticket(&user);
+            lottery_client.commit(&user, &commitment_for(&e, &secret));
+
+            move_to_ended(&e, &lottery_client);
+            lottery_client.raffle();
+        }
+
+        #[test]
+        fn withheld_reveal_forfeits_tickets() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv {
+                user,
+                xlm_asset_client,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            let other_user = Address::generate(&e);
+            xlm_asset_client.mint(&other_user, &20_000_000_i128);
+            let ticket = lottery_client.buy_ticket(&user);
+            lottery_client.buy_ticket(&other_user);
+            let secret = BytesN::from_array(&e, &[7; 32]);
+            lottery_client.commit(&other_user, &commitment_for(&e, &secret));
+
+            move_to_ended(&e, &lottery_client);
+            set_ledger(&e, 300_000 + default_phase_durations().reveal_ledgers);
+            let winners = lottery_client.raffle();
+
+            assert_eq!(1, winners.len());
+            assert_eq!(ticket.id, winners.get_unchecked(0).id);
+        }
+
+        #[test]
+        fn withheld_reveal_forfeit_follows_moved_tickets() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv {
+                user,
+                xlm_asset_client,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            let other_user = Address::generate(&e);
+            let other_wallet = Address::generate(&e);
+            xlm_asset_client.mint(&other_user, &20_000_000_i128);
+            let ticket = lottery_client.buy_ticket(&user);
+            let other_ticket = lottery_client.buy_ticket(&other_user);
+            let secret = BytesN::from_array(&e, &[7; 32]);
+            lottery_client.commit(&other_user, &commitment_for(&e, &secret));
+            lottery_client.transfer(&other_user, &other_wallet, &other_ticket.id);
+
+            move_to_ended(&e, &lottery_client);
+            set_ledger(&e, 300_000 + default_phase_durations().reveal_ledgers);
+            let winners = lottery_client.raffle();
+
+            assert_eq!(1, winners.len());
+            assert_eq!(ticket.id, winners.get_unchecked(0).id);
+        }
+
+        #[test]
+        fn raffle_completes_when_every_ticket_is_forfeited() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            let secret = BytesN::from_array(&e, &[7; 32]);
+            let ticket = lottery_client.buy_ticket(&user);
+            lottery_client.commit(&user, &commitment_for(&e, &secret));
+
+            move_to_ended(&e, &lottery_client);
+            set_ledger(&e, 300_000 + default_phase_durations().reveal_ledgers);
+            let winners = lottery_client.raffle();
+
+            assert!(winners.is_empty());
+            assert!(!lottery_client.get_ticket_metadata(&ticket.id).won);
+            set_ledger(&e, 400_000);
+            lottery_client.set_status(&LotteryStatus::BuyIn);
+        }
+
+        #[test]
+        fn commitments_kept_per_round() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            let secret = BytesN::from_array(&e, &[7; 32]);
+            lottery_client.buy_ticket(&user);
+            lottery_client.commit(&user, &commitment_for(&e, &secret));
+
+            move_to_ended(&e, &lottery_client);
+            lottery_client.reveal(&user, &secret);
+            lottery_client.raffle();
+            set_ledger(&e, 400_000);
+            lottery_client.set_status(&LotteryStatus::BuyIn);
+
+            assert!(lottery_client.get_commitment(&1, &user).unwrap().revealed);
+            assert_eq!(None, lottery_client.get_commitment(&2, &user));
+            lottery_client.commit(&user, &commitment_for(&e, &secret));
+            assert_eq!(1, lottery_client.get_randomness(&2).commitments);
+        }
+
+        fn winner_for_reveal_order(user_reveals_first: bool) -> u32 {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv {
+                user,
+                xlm_asset_client,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            let other_user = Address::generate(&e);
+            xlm_asset_client.mint(&other_user, &20_000_000_i128);
+            lottery_client.buy_ticket(&user);
+            lottery_client.buy_ticket(&other_user);
+
+            let secret = BytesN::from_array(&e, &[7; 32]);
+            let other_secret = BytesN::from_array(&e, &[9; 32]);
+            lottery_client.commit(&user, &commitment_for(&e, &secret));
+            lottery_client.commit(&other_user, &commitment_for(&e, &other_secret));
+
+            move_to_ended(&e, &lottery_client);
+            if user_reveals_first {
+                lottery_client.reveal(&user, &secret);
+                lottery_client.reveal(&other_user, &other_secret);
+            } else {
+                lottery_client.reveal(&other_user, &other_secret);
+                lottery_client.reveal(&user, &secret);
+            }
+            lottery_client.raffle().get_unchecked(0).id
+        }
+
+        #[test]
+        fn reveal_order_does_not_change_winner() {
+            assert_eq!(
+                winner_for_reveal_order(true),
+                winner_for_reveal_order(false)
+            );
+        }
+
+        // The ledger timing is fixed, which is everything the caller of
+        // `set_status(Ended)` controls. The winner still depends on the
+        // secret of the other committer.
+        fn winner_for_secret(secret: [u8; 32]) -> u32 {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv {
+                user,
+                xlm_asset_client,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            let other_user = Address::generate(&e);
+            xlm_asset_client.mint(&other_user, &20_000_000_i128);
+            lottery_client.buy_ticket(&user);
+            lottery_client.buy_ticket(&other_user);
+
+            let secret = BytesN::from_array(&e, &secret);
+            lottery_client.commit(&other_user, &commitment_for(&e, &secret));
+
+            move_to_ended(&e, &lottery_client);
+            lottery_client.reveal(&other_user, &secret);
+            lottery_client.raffle().get_unchecked(0).id
+        }
+
+        #[test]
+        fn single_party_cannot_pick_winner() {
+            let mut first_ticket_wins = 0;
+            for i in 0..8 {
+                if winner_for_secret([i; 32]) == 1 {
+                    first_ticket_wins += 1;
+                }
+            }
+
+            assert!(first_ticket_wins > 0);
+            assert!(first_ticket_wins < 8);
+        }
+
+        // Nobody commits and the ledger timing is fixed. The winner still
+        // depends on the host PRNG of the raffle call.
+        fn winner_for_prng_seed(prng_seed: [u8; 32]) -> u32 {
+            let e = Env::default();
+            e.mock_all_auths();
+            e.host().set_base_prng_seed(prng_seed).unwrap();
+            set_ledger(&e, 1);
+            let TestEnv {
+                user,
+                xlm_asset_client,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            let other_user = Address::generate(&e);
+            xlm_asset_client.mint(&other_user, &20_000_000_i128);
+            lottery_client.buy_ticket(&user);
+            lottery_client.buy_ticket(&other_user);
+
+            move_to_ended(&e, &lottery_client);
+            lottery_client.raffle().get_unchecked(0).id
+        }
+
+        #[test]
+        fn ending_the_round_does_not_pick_winner() {
+            let mut first_ticket_wins = 0;
+            for i in 0..8 {
+                if winner_for_prng_seed([i; 32]) == 1 {
+                    first_ticket_wins += 1;
+                }
+            }
+
+            assert!(first_ticket_wins > 0);
+            assert!(first_ticket_wins < 8);
+        }
+    }
+
+    mod upgrade {
+        use super::*;
+
+        const POOL_WASM: &[u8] = include_bytes!("../../wasm/pool.wasm");
+
+        #[test]
+        fn schedule_waits_a_full_round() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+            let wasm_hash = BytesN::from_array(&e, &[1; 32]);
+
+            let pending = lottery_client.schedule_upgrade(&wasm_hash);
+
+            assert_eq!(wasm_hash, pending.wasm_hash);
+            assert_eq!(1 + 17_300 + 103_800 + 17_300, pending.unlock_ledger);
+            assert_eq!(Some(pending), lottery_client.get_pending_upgrade());
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #21)")]
+        fn upgrade_before_unlock() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+            let wasm_hash = e.deployer().upload_contract_wasm(POOL_WASM);
+
+            lottery_client.schedule_upgrade(&wasm_hash);
+            set_ledger(&e, 138_400);
+            lottery_client.upgrade(&wasm_hash);
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #36)")]
+        fn upgrade_with_other_hash() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+
+            lottery_client.schedule_upgrade(&BytesN::from_array(&e, &[1; 32]));
+            set_ledger(&e, 138_401);
+            lottery_client.upgrade(&BytesN::from_array(&e, &[2; 32]));
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #36)")]
+        fn upgrade_after_cancel() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+            let wasm_hash = BytesN::from_array(&e, &[1; 32]);
+
+            lottery_client.schedule_upgrade(&wasm_hash);
+            lottery_client.cancel_upgrade();
+            assert_eq!(None, lottery_client.get_pending_upgrade());
+
+            set_ledger(&e, 138_401);
+            lottery_client.upgrade(&wasm_hash);
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #36)")]
+        fn cancel_without_pending() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+
+            lottery_client.cancel_upgrade();
+        }
+
+        #[test]
+        fn upgrade_after_unlock() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+            let wasm_hash = e.deployer().upload_contract_wasm(POOL_WASM);
+
+            lottery_client.schedule_upgrade(&wasm_hash);
+            set_ledger(&e, 138_401);
+            lottery_client.upgrade(&wasm_hash);
+
+            assert_eq!(
+                vec![&e, last_event(&e, &lottery_client.address)],
+                vec![
+                    &e,
+                    (
+                        lottery_client.address.clone(),
+                        (Symbol::new(&e, "upgraded"),).into_val(&e),
+                        wasm_hash.into_val(&e),
+                    )
+                ]
+            );
+        }
+
+        #[test]
+        fn new_deployment_is_current() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+
+            assert_eq!(storage::SCHEMA_VERSION, lottery_client.get_schema_version());
+        }
+    }
+
+    mod ttl {
+        use super::*;
+        use soroban_sdk::testutils::storage::{Instance as _, Persistent as _};
+
+        // Mainnet-like limits, so entries expire unless something extends them
+        fn set_mainnet_ledger(e: &Env, sequence_number: u32) {
+            e.ledger().with_mut(|li| {
+                li.sequence_number = sequence_number;
+                li.min_persistent_entry_ttl = 4_096;
+                li.min_temp_entry_ttl = 16;
+                li.max_entry_ttl = 3_110_400;
+            });
+        }
+
+        #[test]
+        fn round_outlives_min_ttl() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_mainnet_ledger(&e, 1);
+            let TestEnv {
+                admin,
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            let ticket = lottery_client.buy_ticket(&user);
+            set_mainnet_ledger(&e, 17_301);
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            lottery_client.blend_it(&admin);
+            set_mainnet_ledger(&e, 121_101);
+            lottery_client.set_status(&LotteryStatus::Ended);
+            lottery_client.withdraw_from_blend(&admin);
+            lottery_client.raffle();
+
+            lottery_client.redeem_ticket_by_id(&user, &ticket.id);
+            assert!(lottery_client.get_user_tickets(&user).is_empty());
+        }
+
+        #[test]
+        fn bump_ticket_keeps_idle_ticket() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_mainnet_ledger(&e, 1);
+            let TestEnv {
+                user,
+                xlm_asset_client,
+                xlm_address,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            for _ in 0..2 {
+                let other = Address::generate(&e);
+                xlm_asset_client.mint(&other, &20_000_000_i128);
+                lottery_client.buy_ticket(&other);
+            }
+            let ticket = lottery_client.buy_ticket(&user);
+
+            // Nobody touches the contract for close to a month
+            set_mainnet_ledger(&e, storage::TTL_EXTEND_AMOUNT - 1);
+            lottery_client.bump_ticket(&ticket.id);
+
+            // The third ticket sits at index 2: its update path is node 3,
+            // the prefix before it node 2 and the pool total nodes 3 and 2
+            let keys = [
+                storage::Key::Ticket(ticket.id),
+                storage::Key::LotteryState,
+                storage::Key::Pool(xlm_address.clone()),
+                storage::Key::ActiveTicketIndex(ticket.id),
+                storage::Key::PoolTicket(xlm_address.clone(), 2),
+                storage::Key::TicketEntry(ticket.id),
+                storage::Key::PoolTicketWeight(xlm_address.clone(), 2),
+                storage::Key::PoolTicketWeight(xlm_address.clone(), 3),
+                storage::Key::PoolEntryWeight(xlm_address.clone(), 2),
+                storage::Key::PoolEntryWeight(xlm_address.clone(), 3),
+            ];
+            e.as_contract(&lottery_client.address, || {
+                assert_eq!(storage::TTL_EXTEND_AMOUNT, e.storage().instance().get_ttl());
+                for key in keys.iter() {
+                    assert_eq!(
+                        storage::TTL_EXTEND_AMOUNT,
+                        e.storage().persistent().get_ttl(key)
+                    );
+                }
+            });
+
+            set_mainnet_ledger(&e, 2 * storage::TTL_EXTEND_AMOUNT - 2);
+            assert_eq!(user, lottery_client.owner_of(&ticket.id));
+            lottery_client.redeem_ticket_by_id(&user, &ticket.id);
+        }
+
+        #[test]
+        fn reads_and_writes_extend_ttl() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_mainnet_ledger(&e, 1);
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            // Thirty days of 17_300 ledgers, the day the phase durations use
+            assert_eq!(519_000, storage::TTL_EXTEND_AMOUNT);
+            assert_eq!(501_700, storage::TTL_THRESHOLD);
+
+            let ticket = lottery_client.buy_ticket(&user);
+            let ttls = || {
+                e.as_contract(&lottery_client.address, || {
+                    (
+                        e.storage().instance().get_ttl(),
+                        e.storage()
+                            .persistent()
+                            .get_ttl(&storage::Key::Ticket(ticket.id)),
+                        e.storage()
+                            .persistent()
+                            .get_ttl(&storage::Key::LotteryState),
+                    )
+                })
+            };
+            assert_eq!(
+                (
+                    storage::TTL_EXTEND_AMOUNT,
+                    storage::TTL_EXTEND_AMOUNT,
+                    storage::TTL_EXTEND_AMOUNT
+                ),
+                ttls()
+            );
+
+            // Above the threshold nothing is extended
+            set_mainnet_ledger(&e, 1_001);
+            lottery_client.get_lottery_state();
+            assert_eq!(storage::TTL_EXTEND_AMOUNT - 1_000, ttls().0);
+
+            set_mainnet_ledger(&e, 2 * storage::DAY_IN_LEDGERS);
+            lottery_client.bump_ticket(&ticket.id);
+            let (instance_ttl, ticket_ttl, state_ttl) = ttls();
+            assert_eq!(storage::TTL_EXTEND_AMOUNT, instance_ttl);
+            assert_eq!(storage::TTL_EXTEND_AMOUNT, ticket_ttl);
+            assert_eq!(
+                storage::TTL_EXTEND_AMOUNT + 1 - 2 * storage::DAY_IN_LEDGERS,
+                state_ttl
+            );
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #6)")]
+        fn bump_unknown_ticket() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+
+            lottery_client.bump_ticket(&1);
+        }
+    }
+
+    mod ticket_odds {
+        use super::*;
+
+        #[test]
+        fn grow_with_time_held() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            let ticket = lottery_client.buy_ticket(&user);
+            assert_eq!(
+                TicketOdds {
+                    weight: 0,
+                    total_weight: 0,
+                    ledger: 1,
+                },
+                lottery_client.get_ticket_odds(&ticket.id)
+            );
+
+            set_ledger(&e, 101);
+            assert_eq!(
+                TicketOdds {
+                    weight: 1_000_000_000,
+                    total_weight: 1_000_000_000,
+                    ledger: 101,
+                },
+                lottery_client.get_ticket_odds(&ticket.id)
+            );
+        }
+
+        #[test]
+        fn late_entrant_weighs_less() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv {
+                user,
+                xlm_asset_client,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+            let late_user = Address::generate(&e);
+            xlm_asset_client.mint(&late_user, &10_000_000_i128);
+
+            let early = lottery_client.buy_ticket(&user);
+            set_ledger(&e, 17_300);
+            let late = lottery_client.buy_ticket(&late_user);
+
+            set_ledger(&e, 17_301);
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            set_ledger(&e, 121_101);
+            lottery_client.set_status(&LotteryStatus::Ended);
+
+            // Measured up to the end of farming, however late it is queried
+            set_ledger(&e, 130_000);
+            let early_odds = lottery_client.get_ticket_odds(&early.id);
+            let late_odds = lottery_client.get_ticket_odds(&late.id);
+            assert_eq!(121_101, early_odds.ledger);
+            assert_eq!(1_211_000_000_000, early_odds.weight);
+            assert_eq!(1_038_010_000_000, late_odds.weight);
+            assert_eq!(2_249_010_000_000, early_odds.total_weight);
+        }
+
+        #[test]
+        fn top_up_accrues_from_deposit() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            let ticket = lottery_client.deposit(&user, &1_000_000);
+            set_ledger(&e, 11);
+            lottery_client.deposit(&user, &2_000_000);
+
+            set_ledger(&e, 21);
+            assert_eq!(
+                40_000_000,
+                lottery_client.get_ticket_odds(&ticket.id).weight
+            );
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #6)")]
+        fn unknown_ticket() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+
+            lottery_client.get_ticket_odds(&1);
+        }
+
+        // With a long buy-in the late ticket is held for about a thousandth
+        // of the early one's time, so it should lose every one of these draws
+        fn early_entrant_wins(sequence_number: u32) -> bool {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, sequence_number);
+            let TestEnv {
+                user,
+                xlm_asset_client,
+                lottery_client,
+                ..
+            } = setup_test_env_with_durations(
+                &e,
+                PhaseDurations {
+                    buyin_ledgers: 100_000,
+                    farming_ledgers: 100,
+                    ended_ledgers: 100,
+                    reveal_ledgers: 0,
+                },
+            );
+            let late_user = Address::generate(&e);
+            xlm_asset_client.mint(&late_user, &10_000_000_i128);
+
+            lottery_client.buy_ticket(&user);
+            set_ledger(&e, sequence_number + 99_999);
+            lottery_client.buy_ticket(&late_user);
+
+            set_ledger(&e, sequence_number + 100_000);
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            set_ledger(&e, sequence_number + 100_100);
+            lottery_client.set_status(&LotteryStatus::Ended);
+            lottery_client.raffle().get_unchecked(0).user == user
+        }
+
+        #[test]
+        fn raffle_weighted_by_time() {
+            let mut early_wins = 0;
+            for i in 0..8 {
+                if early_entrant_wins(1 + i * 1_000) {
+                    early_wins += 1;
+                }
+            }
+
+            assert!(early_wins >= 7);
+        }
+    }
+
+    mod ticket_index {
+        use super::*;
+
+        const MAX_CPU_INSTRUCTIONS: u64 = 100_000_000;
+        const MAX_MEMORY_BYTES: u64 = 41_943_040;
+
+        fn active_tickets(e: &Env, lottery: &Address) -> soroban_sdk::Vec<u32> {
+            e.as_contract(lottery, || {
+                let token = storage::read_currency(e).unwrap();
+                let mut ids = soroban_sdk::Vec::new(e);
+                for index in 0..storage::read_active_ticket_count(e, &token) {
+                    ids.push_back(storage::read_active_ticket(e, &token, index).unwrap());
+                }
+                ids
+            })
+        }
+
+        #[test]
+        fn redeem_swaps_last_ticket_in() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            for _ in 0..4 {
+                lottery_client.buy_ticket(&user);
+            }
+            assert_eq!(
+                vec![&e, 1, 2, 3, 4],
+                active_tickets(&e, &lottery_client.address)
+            );
+
+            lottery_client.redeem_ticket_by_id(&user, &2);
+            assert_eq!(
+                vec![&e, 1, 4, 3],
+                active_tickets(&e, &lottery_client.address)
+            );
+
+            lottery_client.redeem_ticket_by_id(&user, &3);
+            assert_eq!(vec![&e, 1, 4], active_tickets(&e, &lottery_client.address));
+
+            lottery_client.buy_ticket(&user);
+            assert_eq!(
+                vec![&e, 1, 4, 5],
+                active_tickets(&e, &lottery_client.address)
+            );
+        }
+
+        fn weights(e: &Env, lottery: &Address, ledger: u32) -> soroban_sdk::Vec<i128> {
+            e.as_contract(lottery, || {
+                let token = storage::read_currency(e).unwrap();
+                let no_tickets = storage::read_active_ticket_count(e, &token);
+                let mut weights = soroban_sdk::Vec::new(e);
+                let mut previous = 0;
+                for index in 1..=no_tickets {
+                    let prefix = util::total_weight(e, &token, &Map::new(e), ledger, index);
+                    weights.push_back(prefix - previous);
+                    previous = prefix;
+                }
+                weights
+            })
+        }
+
+        #[test]
+        fn weights_follow_swaps() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv {
+                user,
+                xlm_asset_client,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+            let whale = Address::generate(&e);
+            xlm_asset_client.mint(&whale, &100_000_000_i128);
+
+            lottery_client.buy_ticket(&user);
+            lottery_client.deposit(&whale, &50_000_000);
+            lottery_client.buy_ticket(&user);
+            set_ledger(&e, 11);
+            lottery_client.deposit(&whale, &25_000_000);
+            assert_eq!(
+                vec![&e, 200_000_000, 1_250_000_000, 200_000_000],
+                weights(&e, &lottery_client.address, 21)
+            );
+
+            lottery_client.redeem_ticket_by_id(&user, &1);
+            assert_eq!(vec![&e, 3, 2], active_tickets(&e, &lottery_client.address));
+            assert_eq!(
+                vec![&e, 200_000_000, 1_250_000_000],
+                weights(&e, &lottery_client.address, 21)
+            );
+
+            set_ledger(&e, 16);
+            lottery_client.deposit(&user, &5_000_000);
+            assert_eq!(
+                vec![&e, 200_000_000, 1_250_000_000, 25_000_000],
+                weights(&e, &lottery_client.address, 21)
+            );
+        }
+
+        #[test]
+        fn budget_at_10k_tickets() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv {
+                admin,
+                user,
+                xlm_asset_client,
+                xlm_address,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+            lottery_client.set_prize_tiers(&vec![
+                &e,
+                PrizeTier { share_bps: 5_000 },
+                PrizeTier { share_bps: 3_000 },
+                PrizeTier { share_bps: 2_000 },
+            ]);
+
+            lottery_client.buy_ticket(&user);
+            let first_buy_cpu = e.cost_estimate().budget().cpu_instruction_cost();
+
+            // Seeding through `buy_ticket` takes minutes, so write the other
+            // tickets straight to storage
+            e.cost_estimate().budget().reset_unlimited();
+            e.as_contract(&lottery_client.address, || {
+                let token_amount = storage::read_pool(&e, &xlm_address).unwrap().ticket_amount;
+                for _ in 1..9_999 {
+                    let ticket_id = storage::get_and_increment_ticket_counter(&e);
+                    storage::write_ticket(
+                        &e,
+                        &Ticket {
+                            id: ticket_id,
+                            user: user.clone(),
+                            token: xlm_address.clone(),
+                            amount: token_amount,
+                            won: false,
+                            round_id: 1,
+                            principal: token_amount,
+                        },
+                    );
+                    Enumerable::non_sequential_mint(&e, &user, ticket_id);
+                }
+                let mut pool = storage::read_pool(&e, &xlm_address).unwrap();
+                pool.principal += token_amount * 9_998;
+                storage::write_pool(&e, &pool);
+            });
+            xlm_asset_client.mint(&lottery_client.address, &99_980_000_000_i128);
+
+            // Buying the 10 000th ticket only adds the weight tree's
+            // logarithmic reads to the cost of buying the first
+            lottery_client.buy_ticket(&user);
+            let buy_cpu = e.cost_estimate().budget().cpu_instruction_cost();
+            assert!(buy_cpu <= first_buy_cpu * 3 / 2);
+
+            set_ledger(&e, 17_301);
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            lottery_client.blend_it(&admin);
+            set_ledger(&e, 121_101);
+            lottery_client.set_status(&LotteryStatus::Ended);
+            lottery_client.withdraw_from_blend(&admin);
+
+            lottery_client.raffle();
+            let budget = e.cost_estimate().budget();
+            assert!(budget.cpu_instruction_cost() < MAX_CPU_INSTRUCTIONS);
+            assert!(budget.memory_bytes_cost() < MAX_MEMORY_BYTES);
+
+            lottery_client.redeem_ticket_by_id(&user, &1);
+            let budget = e.cost_estimate().budget();
+            assert!(budget.cpu_instruction_cost() < MAX_CPU_INSTRUCTIONS);
+            assert!(budget.memory_bytes_cost() < MAX_MEMORY_BYTES);
+        }
+    }
+
+    mod pools {
+        use super::*;
+
+        // Adds a USDC pool with a 5 USDC ticket, listed as Blend's second
+        // reserve.
+        fn add_usdc_pool<'a>(e: &'a Env, test_env: &TestEnv) -> (Address, StellarAssetClient<'a>) {
+            let usdc_address = e
+                .register_stellar_asset_contract_v2(test_env.admin.clone())
+                .address();
+            let usdc_asset_client = StellarAssetClient::new(e, &usdc_address);
+            usdc_asset_client.mint(&test_env.user, &20_000_000_i128);
+            test_env.blend_client.set_reserve_list(&vec![
+                e,
+                test_env.xlm_address.clone(),
+                usdc_address.clone(),
+            ]);
+
+            test_env.lottery_client.add_pool(&usdc_address, &5_000_000);
+            (usdc_address, usdc_asset_client)
+        }
+
+        // Farms both pools through one round, with 1_000 XLM and 300 USDC of
+        // yield, and leaves the lottery in Ended with the funds withdrawn.
+        fn farm_pools(e: &Env, test_env: &TestEnv, usdc: &(Address, StellarAssetClient)) {
+            start_farming(e, test_env, 17_301);
+            accrue_yield(test_env, 1_000);
+            usdc.1.mint(&test_env.blend_address, &300);
+            test_env.blend_client.accrue(&usdc.0, &300);
+            end_farming(e, test_env, 121_101);
+        }
+
+        #[test]
+        fn add_pool() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let test_env = setup_test_env(&e);
+            let (usdc_address, _) = add_usdc_pool(&e, &test_env);
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = &test_env;
+
+            let ticket = lottery_client.buy_pool_ticket(user, &usdc_address);
+
+            assert_eq!(usdc_address, ticket.token);
+            assert_eq!(5_000_000, ticket.amount);
+            assert_eq!(2, lottery_client.get_pools().len());
+            assert_eq!(
+                usdc_address,
+                lottery_client
+                    .get_current_round()
+                    .pools
+                    .get_unchecked(1)
+                    .token
+            );
+            assert_eq!(
+                5_000_000,
+                TokenClient::new(&e, &usdc_address).balance(&lottery_client.address)
+            );
+        }
+
+        #[test]
+        fn pools_draw_separately() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let test_env = setup_test_env(&e);
+            let usdc = add_usdc_pool(&e, &test_env);
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = &test_env;
+            let other = Address::generate(&e);
+            usdc.1.mint(&other, &5_000_000);
+
+            let xlm_ticket = lottery_client.buy_ticket(user);
+            let usdc_ticket = lottery_client.buy_pool_ticket(&other, &usdc.0);
+            farm_pools(&e, &test_env, &usdc);
+            assert_eq!(300, lottery_client.get_prize_pot(&usdc.0));
+
+            let winners = lottery_client.raffle();
+
+            assert_eq!(2, winners.len());
+            assert_eq!(xlm_ticket.id, winners.get_unchecked(0).id);
+            assert_eq!(10_001_000, winners.get_unchecked(0).amount);
+            assert_eq!(usdc_ticket.id, winners.get_unchecked(1).id);
+            assert_eq!(5_000_300, winners.get_unchecked(1).amount);
+
+            lottery_client.redeem_ticket_by_id(&other, &usdc_ticket.id);
+            assert_eq!(5_000_300, TokenClient::new(&e, &usdc.0).balance(&other));
+        }
+
+        #[test]
+        fn pool_below_min_prize_rolls_over() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let test_env = setup_test_env(&e);
+            let usdc = add_usdc_pool(&e, &test_env);
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = &test_env;
+            lottery_client.set_min_prize(&usdc.0, &1_000);
+
+            lottery_client.buy_ticket(user);
+            lottery_client.buy_pool_ticket(user, &usdc.0);
+            farm_pools(&e, &test_env, &usdc);
+            let winners = lottery_client.raffle();
+            set_ledger(&e, 138_401);
+            lottery_client.set_status(&LotteryStatus::BuyIn);
+
+            assert_eq!(1, winners.len());
+            assert_eq!(test_env.xlm_address, winners.get_unchecked(0).token);
+            assert_eq!(0, lottery_client.get_pool(&usdc.0).amount_of_yield);
+            assert_eq!(300, lottery_client.get_jackpot(&usdc.0));
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #37)")]
+        fn unknown_pool() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            lottery_client.buy_pool_ticket(&user, &Address::generate(&e));
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #38)")]
+        fn duplicate_pool() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv {
+                xlm_address,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            lottery_client.add_pool(&xlm_address, &5_000_000);
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #39)")]
+        fn too_many_pools() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv {
+                xlm_address,
+                blend_client,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+            let mut reserves = vec![&e, xlm_address];
+            for _ in 0..storage::MAX_POOLS {
+                reserves.push_back(Address::generate(&e));
+            }
+            blend_client.set_reserve_list(&reserves);
+
+            for token in reserves.slice(1..).iter() {
+                lottery_client.add_pool(&token, &5_000_000);
+            }
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #1)")]
+        fn add_pool_while_farming() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+
+            set_ledger(&e, 17_301);
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            lottery_client.add_pool(&Address::generate(&e), &5_000_000);
+        }
+    }
+
+    mod protocol_fee {
+        use super::*;
+
+        #[test]
+        fn fee_taken_from_yield() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let test_env = setup_test_env(&e);
+            let treasury = Address::generate(&e);
+            test_env.lottery_client.set_protocol_fee(&1_000, &treasury);
+
+            set_ledger(&e, 1);
+            let ticket = test_env.lottery_client.buy_ticket(&test_env.user);
+            farm_round(&e, &test_env, 1, 1_000);
+
+            let lottery_client = &test_env.lottery_client;
+            assert_eq!(100, lottery_client.get_accrued_fees(&test_env.xlm_address));
+            assert_eq!(
+                900,
+                lottery_client
+                    .get_pool(&test_env.xlm_address)
+                    .amount_of_yield
+            );
+            assert_eq!(
+                900,
+                lottery_client
+                    .get_current_round()
+                    .pools
+                    .get_unchecked(0)
+                    .amount_of_yield
+            );
+
+            let winners = lottery_client.raffle();
+            assert_eq!(ticket.id, winners.get_unchecked(0).id);
+            assert_eq!(10_000_900, winners.get_unchecked(0).amount);
+        }
+
+        #[test]
+        fn no_fee_by_default() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let test_env = setup_test_env(&e);
+
+            set_ledger(&e, 1);
+            test_env.lottery_client.buy_ticket(&test_env.user);
+            farm_round(&e, &test_env, 1, 1_000);
+
+            let lottery_client = &test_env.lottery_client;
+            assert_eq!(None, lottery_client.get_protocol_fee());
+            assert_eq!(0, lottery_client.get_accrued_fees(&test_env.xlm_address));
+            assert_eq!(
+                1_000,
+                lottery_client
+                    .get_pool(&test_env.xlm_address)
+                    .amount_of_yield
+            );
+        }
+
+        #[test]
+        fn withdraw_fees() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let test_env = setup_test_env(&e);
+            let treasury = Address::generate(&e);
+            test_env.lottery_client.set_protocol_fee(&500, &treasury);
+            set_ledger(&e, 1);
+            test_env.lottery_client.buy_ticket(&test_env.user);
+            farm_round(&e, &test_env, 1, 1_000);
+
+            let withdrawn = test_env.lottery_client.withdraw_fees(&test_env.xlm_address);
+
+            assert_eq!(50, withdrawn);
+            assert_eq!(50, test_env.xlm_token_client.balance(&treasury));
+            assert_eq!(
+                0,
+                test_env
+                    .lottery_client
+                    .get_accrued_fees(&test_env.xlm_address)
+            );
+            assert_eq!(
+                0,
+                test_env.lottery_client.withdraw_fees(&test_env.xlm_address)
+            );
+        }
+
+        #[test]
+        fn set_protocol_fee() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let test_env = setup_test_env(&e);
+            let treasury = Address::generate(&e);
+
+            test_env
+                .lottery_client
+                .set_protocol_fee(&storage::MAX_PROTOCOL_FEE_BPS, &treasury);
+
+            let fee = ProtocolFee {
+                fee_bps: storage::MAX_PROTOCOL_FEE_BPS,
+                treasury,
+            };
+            assert_eq!(
+                Some(fee.clone()),
+                test_env.lottery_client.get_protocol_fee()
+            );
+            assert_eq!(
+                vec![&e, last_event(&e, &test_env.lottery_client.address)],
+                vec![
+                    &e,
+                    (
+                        test_env.lottery_client.address.clone(),
+                        (Symbol::new(&e, "protocol_fee_updated"),).into_val(&e),
+                        fee.into_val(&e),
+                    )
+                ]
+            );
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #40)")]
+        fn fee_above_cap() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let test_env = setup_test_env(&e);
+
+            test_env
+                .lottery_client
+                .set_protocol_fee(&(storage::MAX_PROTOCOL_FEE_BPS + 1), &test_env.admin);
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #41)")]
+        fn withdraw_fees_without_fee() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let test_env = setup_test_env(&e);
+
+            test_env.lottery_client.withdraw_fees(&test_env.xlm_address);
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #16)")]
+        fn withdraw_fees_in_blender() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let test_env = setup_test_env(&e);
+            test_env
+                .lottery_client
+                .set_protocol_fee(&1_000, &test_env.admin);
+            test_env.lottery_client.buy_ticket(&test_env.user);
+            start_farming(&e, &test_env, 17_301);
+
+            test_env.lottery_client.withdraw_fees(&test_env.xlm_address);
+        }
+    }
+
+    mod emissions {
+        use super::*;
+        use crate::events::{EmissionsAwardedEvent, EmissionsSwappedEvent};
+
+        // Sets up a BLND token with 500 claimable from Blend and a router
+        // holding 1_000 XLM, and switches the lottery to `mode`.
+        fn setup_emissions(e: &Env, test_env: &TestEnv, mode: EmissionsMode) -> (Address, Address) {
+            let blnd_address = e
+                .register_stellar_asset_contract_v2(test_env.admin.clone())
+                .address();
+            StellarAssetClient::new(e, &blnd_address).mint(&test_env.blend_address, &500);
+            test_env.blend_client.set_emissions_token(&blnd_address);
+            test_env.blend_client.set_emissions(&500);
+
+            let router_address = e.register(mock_router::MockRouter, ());
+            test_env.xlm_asset_client.mint(&router_address, &1_000);
+
+            test_env
+                .lottery_client
+                .set_emissions_config(&EmissionsConfig {
+                    mode,
+                    blnd_token: blnd_address.clone(),
+                    router: Some(router_address.clone()),
+                });
+            (blnd_address, router_address)
+        }
+
+        #[test]
+        fn admin_mode_by_default() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let test_env = setup_test_env(&e);
+
+            test_env.blend_client.set_emissions(&500);
+            test_env.lottery_client.admin_claim_emissions();
+
+            assert_eq!(None, test_env.lottery_client.get_emissions_config());
+            assert_eq!(500, test_env.lottery_client.get_current_round().emissions);
+        }
+
+        #[test]
+        fn swapped_into_prize_pot() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let test_env = setup_test_env(&e);
+            let (blnd_address, router_address) =
+                setup_emissions(&e, &test_env, EmissionsMode::Swap);
+
+            let claimed = test_env
+                .lottery_client
+                .claim_emissions(&test_env.admin, &1_000);
+
+            assert_eq!(500, claimed);
+            assert_eq!(500, test_env.lottery_client.get_current_round().emissions);
+            assert_eq!(
+                1_000,
+                test_env.lottery_client.get_prize_pot(&test_env.xlm_address)
+            );
+            assert_eq!(
+                500,
+                TokenClient::new(&e, &blnd_address).balance(&router_address)
+            );
+            assert_eq!(
+                vec![&e, last_event(&e, &test_env.lottery_client.address)],
+                vec![
+                    &e,
+                    (
+                        test_env.lottery_client.address.clone(),
+                        (
+                            Symbol::new(&e, "emissions_swapped"),
+                            test_env.xlm_address.clone()
+                        )
+                            .into_val(&e),
+                        EmissionsSwappedEvent {
+                            amount_in: 500,
+                            amount_out: 1_000,
+                        }
+                        .into_val(&e),
+                    )
+                ]
+            );
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #44)")]
+        fn swap_below_minimum() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let test_env = setup_test_env(&e);
+            setup_emissions(&e, &test_env, EmissionsMode::Swap);
+
+            test_env
+                .lottery_client
+                .claim_emissions(&test_env.admin, &1_001);
+        }
 
         #[test]
-        fn status_buyin() {
+        fn paid_to_winner() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let test_env = setup_test_env(&e);
+            let (blnd_address, _) = setup_emissions(&e, &test_env, EmissionsMode::Prize);
+            let ticket = test_env.lottery_client.buy_ticket(&test_env.user);
+
+            start_farming(&e, &test_env, 17_301);
+            test_env.lottery_client.claim_emissions(&test_env.admin, &0);
+            end_farming(&e, &test_env, 121_101);
+            test_env.lottery_client.raffle();
+
+            let blnd_client = TokenClient::new(&e, &blnd_address);
+            assert_eq!(500, blnd_client.balance(&test_env.user));
+            assert_eq!(0, blnd_client.balance(&test_env.lottery_client.address));
+            assert_eq!(
+                vec![&e, last_event(&e, &test_env.lottery_client.address)],
+                vec![
+                    &e,
+                    (
+                        test_env.lottery_client.address.clone(),
+                        (Symbol::new(&e, "emissions_awarded"), test_env.user.clone()).into_val(&e),
+                        EmissionsAwardedEvent {
+                            round_id: 1,
+                            ticket_id: ticket.id,
+                            amount: 500,
+                        }
+                        .into_val(&e),
+                    )
+                ]
+            );
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #43)")]
+        fn admin_claim_in_prize_mode() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let test_env = setup_test_env(&e);
+            setup_emissions(&e, &test_env, EmissionsMode::Prize);
+
+            test_env.lottery_client.admin_claim_emissions();
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #43)")]
+        fn claim_in_admin_mode() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let test_env = setup_test_env(&e);
+
+            test_env.lottery_client.claim_emissions(&test_env.admin, &0);
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #42)")]
+        fn swap_without_router() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let test_env = setup_test_env(&e);
+
+            test_env
+                .lottery_client
+                .set_emissions_config(&EmissionsConfig {
+                    mode: EmissionsMode::Swap,
+                    blnd_token: Address::generate(&e),
+                    router: None,
+                });
+        }
+    }
+
+    mod reserve_index {
+        use super::*;
+
+        #[test]
+        fn resolved_at_construction() {
             let e = Env::default();
             e.mock_all_auths();
             let TestEnv {
-                user,
                 xlm_address,
                 lottery_client,
                 ..
             } = setup_test_env(&e);
 
-            let ticket = lottery_client.buy_ticket(&user.clone());
+            assert_eq!(0, lottery_client.get_reserve_index(&xlm_address));
+        }
 
-            let ticket_should_be = Ticket {
-                amount: 10_000_000,
-                id: 1,
-                user: user.clone(),
-                token: xlm_address,
-                won: false,
-            };
+        #[test]
+        #[should_panic(expected = "Error(Contract, #45)")]
+        fn missing_at_construction() {
+            let e = Env::default();
+            let admin = Address::generate(&e);
+            let xlm_address = e
+                .register_stellar_asset_contract_v2(admin.clone())
+                .address();
+            let blend_address = e.register(mock_blend::MockBlendPool, ());
+
+            e.register(
+                NoLossLottery,
+                (
+                    admin,
+                    xlm_address,
+                    10_000_000_i128,
+                    blend_address,
+                    default_phase_durations(),
+                ),
+            );
+        }
 
-            let user_tickets = lottery_client.get_user_tickets(&user);
+        #[test]
+        #[should_panic(expected = "Error(Contract, #45)")]
+        fn add_pool_missing_reserve() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
 
-            let user_tickets_should_be = vec![&e, ticket_should_be.clone()];
+            lottery_client.add_pool(&Address::generate(&e), &5_000_000);
+        }
 
-            assert_eq!(user_tickets_should_be, user_tickets);
-            assert_eq!(ticket_should_be, ticket);
+        #[test]
+        fn withdraw_after_reorder() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let test_env = setup_test_env(&e);
+            supply_ticket(&e, &test_env);
+            let other = Address::generate(&e);
+            test_env
+                .blend_client
+                .set_reserve_list(&vec![&e, other, test_env.xlm_address.clone()]);
+            accrue_yield(&test_env, 1_000);
+
+            end_farming(&e, &test_env, 121_101);
+
+            let lottery_client = &test_env.lottery_client;
+            assert_eq!(1, lottery_client.get_reserve_index(&test_env.xlm_address));
+            assert_eq!(
+                1_000,
+                lottery_client
+                    .get_pool(&test_env.xlm_address)
+                    .amount_of_yield
+            );
+            assert_eq!(
+                10_001_000,
+                test_env.xlm_token_client.balance(&lottery_client.address)
+            );
         }
 
         #[test]
-        #[should_panic(expected = "Error(Contract, #1)")]
-        fn status_yieldfarming() {
+        #[should_panic(expected = "Error(Contract, #45)")]
+        fn withdraw_after_reserve_removed() {
             let e = Env::default();
             e.mock_all_auths();
-            e.ledger().with_mut(|li| {
-                li.sequence_number = 1;
-                li.min_persistent_entry_ttl = 10_000_000;
-                li.min_temp_entry_ttl = 1_000_000;
-                li.max_entry_ttl = 1_000_001;
-            });
+            let test_env = setup_test_env(&e);
+            supply_ticket(&e, &test_env);
+            test_env
+                .blend_client
+                .set_reserve_list(&vec![&e, Address::generate(&e)]);
+
+            end_farming(&e, &test_env, 121_101);
+        }
+
+        #[test]
+        fn refresh_after_reorder() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv {
+                xlm_address,
+                blend_client,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+            let other = Address::generate(&e);
+
+            blend_client.set_reserve_list(&vec![&e, other, xlm_address.clone()]);
+            lottery_client.refresh_reserve_indexes();
+
+            assert_eq!(1, lottery_client.get_reserve_index(&xlm_address));
+            assert_eq!(
+                vec![&e, last_event(&e, &lottery_client.address)],
+                vec![
+                    &e,
+                    (
+                        lottery_client.address.clone(),
+                        (Symbol::new(&e, "reserve_index_updated"), xlm_address).into_val(&e),
+                        1_u32.into_val(&e),
+                    )
+                ]
+            );
+        }
+    }
+
+    mod pending_yield {
+        use super::*;
 
+        #[test]
+        fn zero_before_supplying() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
             let TestEnv {
                 user,
+                xlm_address,
                 lottery_client,
                 ..
             } = setup_test_env(&e);
+            lottery_client.buy_ticket(&user);
 
-            e.ledger().with_mut(|li| {
-                li.sequence_number = 100_000;
-            });
+            assert_eq!(0, lottery_client.get_pending_yield(&xlm_address));
+        }
 
-            lottery_client.set_status(&LotteryStatus::YieldFarming);
+        #[test]
+        fn grows_with_b_rate() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let test_env = setup_test_env(&e);
+            supply_ticket(&e, &test_env);
+            let lottery_client = &test_env.lottery_client;
+
+            assert_eq!(0, lottery_client.get_pending_yield(&test_env.xlm_address));
+            accrue_yield(&test_env, 1_000);
+            assert_eq!(
+                1_000,
+                lottery_client.get_pending_yield(&test_env.xlm_address)
+            );
+            accrue_yield(&test_env, 500);
+            assert_eq!(
+                1_500,
+                lottery_client.get_pending_yield(&test_env.xlm_address)
+            );
+        }
 
-            lottery_client.buy_ticket(&user.clone());
+        #[test]
+        fn net_of_protocol_fee() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let test_env = setup_test_env(&e);
+            test_env
+                .lottery_client
+                .set_protocol_fee(&1_000, &Address::generate(&e));
+            supply_ticket(&e, &test_env);
+            accrue_yield(&test_env, 1_000);
+
+            assert_eq!(
+                900,
+                test_env
+                    .lottery_client
+                    .get_pending_yield(&test_env.xlm_address)
+            );
         }
 
         #[test]
-        #[should_panic(expected = "Error(Contract, #1)")]
-        fn status_ended() {
+        fn zero_after_withdrawing() {
             let e = Env::default();
             e.mock_all_auths();
-            e.ledger().with_mut(|li| {
-                li.sequence_number = 1;
-                li.min_persistent_entry_ttl = 10_000_000;
-                li.min_temp_entry_ttl = 1_000_000;
-                li.max_entry_ttl = 1_000_001;
-            });
+            let test_env = setup_test_env(&e);
+            supply_ticket(&e, &test_env);
+            accrue_yield(&test_env, 1_000);
+
+            end_farming(&e, &test_env, 121_101);
+
+            assert_eq!(
+                0,
+                test_env
+                    .lottery_client
+                    .get_pending_yield(&test_env.xlm_address)
+            );
+        }
+
+        #[test]
+        fn withdraw_matches_pending_yield() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let test_env = setup_test_env(&e);
+            supply_ticket(&e, &test_env);
+            accrue_yield(&test_env, 1_000);
+            let lottery_client = &test_env.lottery_client;
+            let pending = lottery_client.get_pending_yield(&test_env.xlm_address);
+
+            set_ledger(&e, 121_101);
+            lottery_client.set_status(&LotteryStatus::Ended);
+            lottery_client.withdraw_from_blend(&test_env.admin);
+
+            assert_eq!(
+                pending,
+                lottery_client
+                    .get_pool(&test_env.xlm_address)
+                    .amount_of_yield
+            );
+        }
+
+        #[test]
+        fn donations_are_not_yield() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let test_env = setup_test_env(&e);
+            supply_ticket(&e, &test_env);
+            let lottery_client = &test_env.lottery_client;
+            accrue_yield(&test_env, 1_000);
+            test_env
+                .xlm_asset_client
+                .mint(&lottery_client.address, &5_000);
+
+            set_ledger(&e, 121_101);
+            lottery_client.set_status(&LotteryStatus::Ended);
+            lottery_client.withdraw_from_blend(&test_env.admin);
+
+            assert_eq!(
+                1_000,
+                lottery_client
+                    .get_pool(&test_env.xlm_address)
+                    .amount_of_yield
+            );
+            assert_eq!(
+                10_006_000,
+                test_env.xlm_token_client.balance(&lottery_client.address)
+            );
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #37)")]
+        fn unknown_pool() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+
+            lottery_client.get_pending_yield(&Address::generate(&e));
+        }
+    }
+
+    mod solvency {
+        use super::*;
+
+        // Runs a round in which the only ticket wins 1 000 of yield.
+        fn win_round(e: &Env, test_env: &TestEnv) -> Ticket {
+            set_ledger(e, 1);
+            let ticket = test_env.lottery_client.buy_ticket(&test_env.user);
+            farm_round(e, test_env, 1, 1_000);
+            test_env.lottery_client.raffle();
+            ticket
+        }
+
+        fn start_next_round(e: &Env, test_env: &TestEnv) {
+            set_ledger(e, 138_401);
+            test_env.lottery_client.set_status(&LotteryStatus::BuyIn);
+            start_farming(e, test_env, 155_701);
+        }
 
+        #[test]
+        fn tracks_principal() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
             let TestEnv {
                 user,
+                xlm_address,
                 lottery_client,
                 ..
             } = setup_test_env(&e);
 
-            e.ledger().with_mut(|li| {
-                li.sequence_number = 100_000;
+            let ticket = lottery_client.buy_ticket(&user);
+            lottery_client.deposit(&user, &5_000_000);
+            assert_eq!(15_000_000, lottery_client.get_pool(&xlm_address).principal);
+            lottery_client.check_solvency();
+
+            lottery_client.redeem_ticket_by_id(&user, &ticket.id);
+            assert_eq!(5_000_000, lottery_client.get_pool(&xlm_address).principal);
+            lottery_client.check_solvency();
+        }
+
+        #[test]
+        fn tracks_unclaimed_prizes() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let test_env = setup_test_env(&e);
+            let ticket = win_round(&e, &test_env);
+            let lottery_client = &test_env.lottery_client;
+
+            let pool = lottery_client.get_pool(&test_env.xlm_address);
+            assert_eq!(10_000_000, pool.principal);
+            assert_eq!(1_000, pool.unclaimed_prizes);
+            assert_eq!(0, pool.amount_of_yield);
+            lottery_client.check_solvency();
+
+            lottery_client.redeem_ticket_by_id(&test_env.user, &ticket.id);
+            let pool = lottery_client.get_pool(&test_env.xlm_address);
+            assert_eq!(0, pool.principal);
+            assert_eq!(0, pool.unclaimed_prizes);
+            lottery_client.check_solvency();
+        }
+
+        #[test]
+        fn prizes_stay_out_of_blend() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let test_env = setup_test_env(&e);
+            win_round(&e, &test_env);
+            start_next_round(&e, &test_env);
+            let lottery_client = &test_env.lottery_client;
+
+            assert_eq!(
+                10_000_000,
+                lottery_client.get_pool(&test_env.xlm_address).sent_balance
+            );
+            assert_eq!(
+                1_000,
+                test_env.xlm_token_client.balance(&lottery_client.address)
+            );
+            lottery_client.check_solvency();
+
+            // Only the principal earns yield for the next prize
+            accrue_yield(&test_env, 500);
+            end_farming(&e, &test_env, 259_501);
+
+            let pool = lottery_client.get_pool(&test_env.xlm_address);
+            assert_eq!(500, pool.amount_of_yield);
+            assert_eq!(1_000, pool.unclaimed_prizes);
+            lottery_client.check_solvency();
+        }
+
+        #[test]
+        fn rolling_redemption_pays_prize_from_contract() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let test_env = setup_test_env(&e);
+            test_env.lottery_client.set_rolling_mode(&true);
+            let ticket = win_round(&e, &test_env);
+            start_next_round(&e, &test_env);
+            let lottery_client = &test_env.lottery_client;
+
+            lottery_client.redeem_ticket_by_id(&test_env.user, &ticket.id);
+
+            assert_eq!(
+                20_001_000,
+                test_env.xlm_token_client.balance(&test_env.user)
+            );
+            let pool = lottery_client.get_pool(&test_env.xlm_address);
+            assert_eq!(0, pool.sent_balance);
+            assert_eq!(0, pool.unclaimed_prizes);
+            lottery_client.check_solvency();
+        }
+
+        #[test]
+        fn fees_are_liabilities() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let test_env = setup_test_env(&e);
+            let treasury = Address::generate(&e);
+            test_env.lottery_client.set_protocol_fee(&1_000, &treasury);
+            win_round(&e, &test_env);
+            let lottery_client = &test_env.lottery_client;
+
+            assert_eq!(100, lottery_client.get_accrued_fees(&test_env.xlm_address));
+            assert_eq!(
+                900,
+                lottery_client
+                    .get_pool(&test_env.xlm_address)
+                    .unclaimed_prizes
+            );
+            lottery_client.check_solvency();
+
+            lottery_client.withdraw_fees(&test_env.xlm_address);
+            lottery_client.check_solvency();
+        }
+
+        #[test]
+        #[should_panic(expected = "Error(Contract, #46)")]
+        fn insolvent() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let test_env = setup_test_env(&e);
+            test_env.lottery_client.buy_ticket(&test_env.user);
+            e.as_contract(&test_env.lottery_client.address, || {
+                let mut pool = storage::read_pool(&e, &test_env.xlm_address).unwrap();
+                pool.unclaimed_prizes += 1;
+                storage::write_pool(&e, &pool);
             });
 
-            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            test_env.lottery_client.check_solvency();
+        }
 
-            e.ledger().with_mut(|li| {
-                li.sequence_number = 300_000;
+        #[test]
+        #[should_panic(expected = "Error(Contract, #46)")]
+        fn insolvent_while_farming() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let test_env = setup_test_env(&e);
+            test_env.lottery_client.buy_ticket(&test_env.user);
+            start_farming(&e, &test_env, 17_301);
+            test_env.lottery_client.check_solvency();
+            e.as_contract(&test_env.lottery_client.address, || {
+                let mut pool = storage::read_pool(&e, &test_env.xlm_address).unwrap();
+                pool.principal += 1;
+                storage::write_pool(&e, &pool);
             });
 
-            lottery_client.set_status(&LotteryStatus::Ended);
+            test_env.lottery_client.check_solvency();
+        }
+    }
 
-            lottery_client.buy_ticket(&user.clone());
+    mod access_control {
+        use super::*;
+        use soroban_sdk::testutils::AuthorizedFunction;
+
+        // True if `address` authorized `function` in the last invocation
+        fn authorized(e: &Env, address: &Address, function: &str) -> bool {
+            e.auths().iter().any(|(authorizer, invocation)| {
+                authorizer == address
+                    && matches!(
+                        &invocation.function,
+                        AuthorizedFunction::Contract((_, name, _)) if *name == Symbol::new(e, function)
+                    )
+            })
         }
 
         #[test]
-        fn buy_multiple() {
+        fn user_entry_points() {
             let e = Env::default();
             e.mock_all_auths();
+            set_ledger(&e, 1);
             let TestEnv {
                 user,
-                xlm_address,
                 lottery_client,
                 ..
             } = setup_test_env(&e);
+            let secret = BytesN::from_array(&e, &[7; 32]);
 
-            let ticket = lottery_client.buy_ticket(&user.clone());
-            let ticket2 = lottery_client.buy_ticket(&user.clone());
+            let ticket = lottery_client.buy_ticket(&user);
+            assert!(authorized(&e, &user, "buy_ticket"));
 
-            let ticket_should_be = Ticket {
-                amount: 10_000_000,
-                id: 1,
-                user: user.clone(),
-                token: xlm_address.clone(),
-                won: false,
-            };
-            let ticket2_should_be = Ticket {
-                amount: 10_000_000,
-                id: 2,
-                user: user.clone(),
-                token: xlm_address,
-                won: false,
-            };
+            lottery_client.deposit(&user, &1_000_000);
+            assert!(authorized(&e, &user, "deposit"));
 
-            let user_tickets = lottery_client.get_user_tickets(&user);
+            lottery_client.commit(
+                &user,
+                &e.crypto().sha256(&Bytes::from(secret.clone())).into(),
+            );
+            assert!(authorized(&e, &user, "commit"));
 
-            let user_tickets_should_be =
-                vec![&e, ticket_should_be.clone(), ticket2_should_be.clone()];
+            set_ledger(&e, 17_301);
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            set_ledger(&e, 121_101);
+            lottery_client.set_status(&LotteryStatus::Ended);
 
-            assert_eq!(user_tickets_should_be, user_tickets);
-            assert_eq!(ticket_should_be, ticket);
-            assert_eq!(ticket2_should_be, ticket2);
-        }
-    }
+            lottery_client.reveal(&user, &secret);
+            assert!(authorized(&e, &user, "reveal"));
 
-    mod redeem_tickets {
-        use super::*;
+            lottery_client.redeem_ticket_by_id(&user, &ticket.id);
+            assert!(authorized(&e, &user, "redeem_ticket_by_id"));
+        }
 
         #[test]
-        fn status_buyin() {
+        fn permissionless_entry_points() {
             let e = Env::default();
             e.mock_all_auths();
+            set_ledger(&e, 1);
             let TestEnv {
+                admin,
                 user,
                 lottery_client,
                 ..
             } = setup_test_env(&e);
 
-            let ticket = lottery_client.buy_ticket(&user.clone());
-            lottery_client.redeem_ticket(&ticket);
+            lottery_client.buy_ticket(&user);
 
-            let user_tickets = lottery_client.get_user_tickets(&user);
+            set_ledger(&e, 17_301);
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            assert!(e.auths().is_empty());
 
-            let user_tickets_should_be = vec![&e];
+            lottery_client.blend_it(&admin);
+            set_ledger(&e, 121_101);
+            lottery_client.set_status(&LotteryStatus::Ended);
+            lottery_client.withdraw_from_blend(&admin);
 
-            assert_eq!(user_tickets_should_be, user_tickets);
-            //TODO: Maybe check the Ids vec?
+            lottery_client.raffle();
+            assert!(e.auths().is_empty());
         }
 
         #[test]
-        #[should_panic(expected = "Error(Contract, #1)")]
-        fn status_yieldfarming() {
+        fn operator_entry_points() {
             let e = Env::default();
             e.mock_all_auths();
-            e.ledger().with_mut(|li| {
-                li.sequence_number = 1;
-                li.min_persistent_entry_ttl = 10_000_000;
-                li.min_temp_entry_ttl = 1_000_000;
-                li.max_entry_ttl = 1_000_001;
-            });
-
+            set_ledger(&e, 1);
             let TestEnv {
                 user,
                 lottery_client,
                 ..
             } = setup_test_env(&e);
+            let operator = Address::generate(&e);
 
-            let ticket = lottery_client.buy_ticket(&user.clone());
-
-            e.ledger().with_mut(|li| {
-                li.sequence_number = 100_000;
-            });
+            lottery_client.grant_role(&Role::Operator, &operator);
+            lottery_client.buy_ticket(&user);
 
+            set_ledger(&e, 17_301);
             lottery_client.set_status(&LotteryStatus::YieldFarming);
-            lottery_client.redeem_ticket(&ticket);
+            lottery_client.blend_it(&operator);
+            assert!(authorized(&e, &operator, "blend_it"));
+
+            set_ledger(&e, 121_101);
+            lottery_client.set_status(&LotteryStatus::Ended);
+            lottery_client.withdraw_from_blend(&operator);
+            assert!(authorized(&e, &operator, "withdraw_from_blend"));
         }
 
         #[test]
-        fn status_ended() {
+        #[should_panic(expected = "Error(Contract, #8)")]
+        fn blend_it_without_role() {
             let e = Env::default();
             e.mock_all_auths();
-            e.ledger().with_mut(|li| {
-                li.sequence_number = 1;
-                li.min_persistent_entry_ttl = 10_000_000;
-                li.min_temp_entry_ttl = 1_000_000;
-                li.max_entry_ttl = 1_000_001;
-            });
-
+            set_ledger(&e, 1);
             let TestEnv {
                 user,
                 lottery_client,
                 ..
             } = setup_test_env(&e);
 
-            let ticket = lottery_client.buy_ticket(&user.clone());
-            e.ledger().with_mut(|li| {
-                li.sequence_number = 100_000;
-            });
+            lottery_client.buy_ticket(&user);
+            set_ledger(&e, 17_301);
             lottery_client.set_status(&LotteryStatus::YieldFarming);
-            e.ledger().with_mut(|li| {
-                li.sequence_number = 300_000;
-            });
-            lottery_client.set_status(&LotteryStatus::Ended);
-            lottery_client.redeem_ticket(&ticket);
+            lottery_client.blend_it(&user);
+        }
 
-            let user_tickets = lottery_client.get_user_tickets(&user);
+        #[test]
+        #[should_panic(expected = "Error(Contract, #8)")]
+        fn withdraw_from_blend_with_revoked_role() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+            let operator = Address::generate(&e);
 
-            let user_tickets_should_be = vec![&e];
+            lottery_client.grant_role(&Role::Operator, &operator);
+            lottery_client.buy_ticket(&user);
+            set_ledger(&e, 17_301);
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            lottery_client.blend_it(&operator);
+            set_ledger(&e, 121_101);
+            lottery_client.set_status(&LotteryStatus::Ended);
 
-            assert_eq!(user_tickets_should_be, user_tickets);
+            lottery_client.revoke_role(&Role::Operator, &operator);
+            lottery_client.withdraw_from_blend(&operator);
         }
 
         #[test]
-        fn redeem_multiple() {
+        #[should_panic(expected = "Error(Contract, #8)")]
+        fn claim_emissions_without_role() {
             let e = Env::default();
             e.mock_all_auths();
             let TestEnv {
@@ -624,222 +5779,612 @@ mod tests {
                 ..
             } = setup_test_env(&e);
 
-            let ticket = lottery_client.buy_ticket(&user.clone());
-            let ticket2 = lottery_client.buy_ticket(&user.clone());
+            lottery_client.set_emissions_config(&EmissionsConfig {
+                mode: EmissionsMode::Prize,
+                blnd_token: Address::generate(&e),
+                router: None,
+            });
+            lottery_client.claim_emissions(&user, &0);
+        }
 
-            lottery_client.redeem_ticket(&ticket);
-            lottery_client.redeem_ticket(&ticket2);
+        #[test]
+        fn keeper_entry_points() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+            let keeper = Address::generate(&e);
 
-            let user_tickets = lottery_client.get_user_tickets(&user);
+            lottery_client.grant_role(&Role::Keeper, &keeper);
 
-            let user_tickets_should_be = vec![&e];
+            lottery_client.commit(&keeper, &BytesN::from_array(&e, &[1; 32]));
+            assert!(authorized(&e, &keeper, "commit"));
 
-            assert_eq!(user_tickets_should_be, user_tickets);
+            set_ledger(&e, 17_301);
+            lottery_client.advance(&keeper);
+            assert!(authorized(&e, &keeper, "advance"));
         }
 
-        //TODO: redeem_user_won?
-    }
+        #[test]
+        #[should_panic(expected = "Error(Contract, #8)")]
+        fn advance_without_role() {
+            let e = Env::default();
+            e.mock_all_auths();
+            set_ledger(&e, 1);
+            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+            let operator = Address::generate(&e);
 
-    mod set_status {
-        use super::*;
+            lottery_client.grant_role(&Role::Operator, &operator);
+            set_ledger(&e, 17_301);
+            lottery_client.advance(&operator);
+        }
 
         #[test]
-        fn set_status_yieldfarming() {
+        fn admin_entry_points() {
             let e = Env::default();
             e.mock_all_auths();
-            e.ledger().with_mut(|li| {
-                li.sequence_number = 1;
-                li.min_persistent_entry_ttl = 10_000_000;
-                li.min_temp_entry_ttl = 1_000_000;
-                li.max_entry_ttl = 1_000_001;
-            });
+            let TestEnv {
+                admin,
+                xlm_address,
+                blend_client,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+            let account = Address::generate(&e);
 
-            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+            lottery_client.grant_role(&Role::Operator, &account);
+            assert!(authorized(&e, &admin, "grant_role"));
 
-            e.ledger().with_mut(|li| {
-                li.sequence_number = 100_000;
-            });
+            lottery_client.revoke_role(&Role::Operator, &account);
+            assert!(authorized(&e, &admin, "revoke_role"));
 
-            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            lottery_client.set_prize_tiers(&vec![&e, PrizeTier { share_bps: 10_000 }]);
+            assert!(authorized(&e, &admin, "set_prize_tiers"));
 
-            let state = lottery_client.get_lottery_state();
+            lottery_client.set_min_prize(&xlm_address, &100);
+            assert!(authorized(&e, &admin, "set_min_prize"));
 
-            assert_eq!(LotteryStatus::YieldFarming, state.status);
-        }
+            lottery_client.set_keeper_bounty(&100);
+            assert!(authorized(&e, &admin, "set_keeper_bounty"));
 
-        #[test]
-        fn set_status_ended() {
-            let e = Env::default();
-            e.mock_all_auths();
-            e.ledger().with_mut(|li| {
-                li.sequence_number = 1;
-                li.min_persistent_entry_ttl = 10_000_000;
-                li.min_temp_entry_ttl = 1_000_000;
-                li.max_entry_ttl = 1_000_001;
-            });
+            lottery_client.set_rolling_mode(&true);
+            assert!(authorized(&e, &admin, "set_rolling_mode"));
 
-            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+            let treasury = Address::generate(&e);
+            lottery_client.set_protocol_fee(&1_000, &treasury);
+            assert!(authorized(&e, &admin, "set_protocol_fee"));
 
-            e.ledger().with_mut(|li| {
-                li.sequence_number = 100_000;
-            });
+            lottery_client.withdraw_fees(&xlm_address);
+            assert!(authorized(&e, &treasury, "withdraw_fees"));
 
-            lottery_client.set_status(&LotteryStatus::YieldFarming);
-            e.ledger().with_mut(|li| {
-                li.sequence_number = 300_000;
+            let usdc_address = Address::generate(&e);
+            blend_client.set_reserve_list(&vec![&e, xlm_address.clone(), usdc_address.clone()]);
+            lottery_client.add_pool(&usdc_address, &1_000_000);
+            assert!(authorized(&e, &admin, "add_pool"));
+
+            lottery_client.set_emissions_config(&EmissionsConfig {
+                mode: EmissionsMode::Admin,
+                blnd_token: Address::generate(&e),
+                router: None,
             });
-            lottery_client.set_status(&LotteryStatus::Ended);
+            assert!(authorized(&e, &admin, "set_emissions_config"));
 
-            let state = lottery_client.get_lottery_state();
+            lottery_client.propose_phase_durations(&default_phase_durations());
+            assert!(authorized(&e, &admin, "propose_phase_durations"));
 
-            assert_eq!(LotteryStatus::Ended, state.status);
+            lottery_client.cancel_phase_durations();
+            assert!(authorized(&e, &admin, "cancel_phase_durations"));
+
+            blend_client.set_emissions(&500);
+            lottery_client.admin_claim_emissions();
+            assert!(authorized(&e, &admin, "admin_claim_emissions"));
+
+            lottery_client.pause(&admin);
+            assert!(authorized(&e, &admin, "pause"));
+
+            lottery_client.unpause(&admin);
+            assert!(authorized(&e, &admin, "unpause"));
+
+            lottery_client.schedule_upgrade(&BytesN::from_array(&e, &[1; 32]));
+            assert!(authorized(&e, &admin, "schedule_upgrade"));
+
+            lottery_client.cancel_upgrade();
+            assert!(authorized(&e, &admin, "cancel_upgrade"));
         }
 
         #[test]
-        fn set_status_buyin() {
+        fn admin_holds_every_role() {
             let e = Env::default();
             e.mock_all_auths();
-            e.ledger().with_mut(|li| {
-                li.sequence_number = 1;
-                li.min_persistent_entry_ttl = 10_000_000;
-                li.min_temp_entry_ttl = 1_000_000;
-                li.max_entry_ttl = 1_000_001;
-            });
+            let TestEnv {
+                admin,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
 
-            let TestEnv { lottery_client, .. } = setup_test_env(&e);
-            e.ledger().with_mut(|li| {
-                li.sequence_number = 100_000;
-            });
-            lottery_client.set_status(&LotteryStatus::YieldFarming);
-            e.ledger().with_mut(|li| {
-                li.sequence_number = 300_000;
-            });
-            lottery_client.set_status(&LotteryStatus::Ended);
-            e.ledger().with_mut(|li| {
-                li.sequence_number = 400_000;
-            });
-            lottery_client.set_status(&LotteryStatus::BuyIn);
+            assert!(lottery_client.has_role(&Role::Operator, &admin));
+            assert!(lottery_client.has_role(&Role::Keeper, &admin));
+            assert!(!lottery_client.has_role(&Role::Keeper, &Address::generate(&e)));
+            assert!(lottery_client.get_role_members(&Role::Keeper).is_empty());
+        }
+    }
 
-            let state = lottery_client.get_lottery_state();
+    mod events {
+        use super::*;
+        use crate::events::{
+            AdvancedEvent, StatusChangedEvent, TicketEvent, WinnerDrawnEvent,
+            WithdrawnFromBlendEvent,
+        };
 
-            assert_eq!(LotteryStatus::BuyIn, state.status);
+        #[test]
+        fn ticket_bought() {
+            let e = Env::default();
+            e.mock_all_auths();
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            lottery_client.buy_ticket(&user);
+
+            assert_eq!(
+                vec![&e, last_event(&e, &lottery_client.address)],
+                vec![
+                    &e,
+                    (
+                        lottery_client.address.clone(),
+                        (Symbol::new(&e, "ticket_bought"), user.clone()).into_val(&e),
+                        TicketEvent {
+                            ticket_id: 1,
+                            amount: 10_000_000,
+                        }
+                        .into_val(&e),
+                    )
+                ]
+            );
         }
 
         #[test]
-        #[should_panic(expected = "Error(Contract, #1)")]
-        fn set_status_ended_after_buyin() {
+        fn ticket_redeemed() {
             let e = Env::default();
             e.mock_all_auths();
-            let TestEnv { lottery_client, .. } = setup_test_env(&e);
-            lottery_client.set_status(&LotteryStatus::Ended);
+            let TestEnv {
+                user,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            let ticket = lottery_client.buy_ticket(&user);
+            lottery_client.redeem_ticket_by_id(&user, &ticket.id);
+
+            assert_eq!(
+                vec![&e, last_event(&e, &lottery_client.address)],
+                vec![
+                    &e,
+                    (
+                        lottery_client.address.clone(),
+                        (Symbol::new(&e, "ticket_redeemed"), user.clone()).into_val(&e),
+                        TicketEvent {
+                            ticket_id: 1,
+                            amount: 10_000_000,
+                        }
+                        .into_val(&e),
+                    )
+                ]
+            );
         }
 
         #[test]
-        #[should_panic(expected = "Error(Contract, #1)")]
-        fn set_status_yieldfarming_after_ended() {
+        fn status_changed() {
             let e = Env::default();
             e.mock_all_auths();
-            e.ledger().with_mut(|li| {
-                li.sequence_number = 1;
-                li.min_persistent_entry_ttl = 10_000_000;
-                li.min_temp_entry_ttl = 1_000_000;
-                li.max_entry_ttl = 1_000_001;
-            });
+            set_ledger(&e, 1);
             let TestEnv { lottery_client, .. } = setup_test_env(&e);
-            e.ledger().with_mut(|li| {
-                li.sequence_number = 100_000;
-            });
-            lottery_client.set_status(&LotteryStatus::YieldFarming);
-            e.ledger().with_mut(|li| {
-                li.sequence_number = 300_000;
-            });
-            lottery_client.set_status(&LotteryStatus::Ended);
+
+            set_ledger(&e, 100_000);
             lottery_client.set_status(&LotteryStatus::YieldFarming);
+
+            assert_eq!(
+                vec![&e, last_event(&e, &lottery_client.address)],
+                vec![
+                    &e,
+                    (
+                        lottery_client.address.clone(),
+                        (Symbol::new(&e, "status_changed"),).into_val(&e),
+                        StatusChangedEvent {
+                            old_status: LotteryStatus::BuyIn,
+                            new_status: LotteryStatus::YieldFarming,
+                        }
+                        .into_val(&e),
+                    )
+                ]
+            );
         }
 
         #[test]
-        #[should_panic(expected = "Error(Contract, #1)")]
-        fn set_status_buyin_after_yieldfarming() {
+        fn advanced() {
             let e = Env::default();
             e.mock_all_auths();
-            e.ledger().with_mut(|li| {
-                li.sequence_number = 1;
-                li.min_persistent_entry_ttl = 10_000_000;
-                li.min_temp_entry_ttl = 1_000_000;
-                li.max_entry_ttl = 1_000_001;
-            });
+            set_ledger(&e, 1);
             let TestEnv { lottery_client, .. } = setup_test_env(&e);
-            e.ledger().with_mut(|li| {
-                li.sequence_number = 100_000;
-            });
-            lottery_client.set_status(&LotteryStatus::YieldFarming);
-            lottery_client.set_status(&LotteryStatus::BuyIn);
+            let keeper = Address::generate(&e);
+            lottery_client.grant_role(&Role::Keeper, &keeper);
+
+            set_ledger(&e, 100_000);
+            lottery_client.advance(&keeper);
+
+            assert_eq!(
+                vec![&e, last_event(&e, &lottery_client.address)],
+                vec![
+                    &e,
+                    (
+                        lottery_client.address.clone(),
+                        (Symbol::new(&e, "advanced"), keeper.clone()).into_val(&e),
+                        AdvancedEvent {
+                            step: AdvanceStep::StartFarming,
+                            bounty: 0,
+                        }
+                        .into_val(&e),
+                    )
+                ]
+            );
         }
-    }
-
-    mod raffle {
-        use super::*;
 
         #[test]
-        #[should_panic(expected = "Error(Contract, #1)")]
-        fn status_buyin() {
+        fn deposited_to_blend() {
             let e = Env::default();
             e.mock_all_auths();
-            let TestEnv { lottery_client, .. } = setup_test_env(&e);
+            set_ledger(&e, 1);
+            let TestEnv {
+                admin,
+                user,
+                xlm_address,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
 
-            lottery_client.raffle();
+            lottery_client.buy_ticket(&user);
+            set_ledger(&e, 100_000);
+            lottery_client.set_status(&LotteryStatus::YieldFarming);
+            lottery_client.blend_it(&admin);
+
+            assert_eq!(
+                vec![&e, last_event(&e, &lottery_client.address)],
+                vec![
+                    &e,
+                    (
+                        lottery_client.address.clone(),
+                        (Symbol::new(&e, "deposited_to_blend"), xlm_address).into_val(&e),
+                        10_000_000_i128.into_val(&e),
+                    )
+                ]
+            );
         }
 
         #[test]
-        #[should_panic(expected = "Error(Contract, #1)")]
-        fn status_yieldfarming() {
+        fn withdrawn_from_blend_and_winner_drawn() {
             let e = Env::default();
             e.mock_all_auths();
-            e.ledger().with_mut(|li| {
-                li.sequence_number = 1;
-                li.min_persistent_entry_ttl = 10_000_000;
-                li.min_temp_entry_ttl = 1_000_000;
-                li.max_entry_ttl = 1_000_001;
-            });
-            let TestEnv { lottery_client, .. } = setup_test_env(&e);
-            e.ledger().with_mut(|li| {
-                li.sequence_number = 100_000;
-            });
+            set_ledger(&e, 1);
+            let TestEnv {
+                admin,
+                user,
+                xlm_asset_client,
+                xlm_address,
+                blend_address,
+                blend_client,
+                lottery_client,
+                ..
+            } = setup_test_env(&e);
+
+            lottery_client.buy_ticket(&user);
+            set_ledger(&e, 100_000);
             lottery_client.set_status(&LotteryStatus::YieldFarming);
+            lottery_client.blend_it(&admin);
+            xlm_asset_client.mint(&blend_address, &1_000);
+            blend_client.accrue(&xlm_address, &1_000);
+            set_ledger(&e, 300_000);
+            lottery_client.set_status(&LotteryStatus::Ended);
+            lottery_client.withdraw_from_blend(&admin);
+
+            assert_eq!(
+                vec![&e, last_event(&e, &lottery_client.address)],
+                vec![
+                    &e,
+                    (
+                        lottery_client.address.clone(),
+                        (Symbol::new(&e, "withdrawn_from_blend"), xlm_address.clone()).into_val(&e),
+                        WithdrawnFromBlendEvent {
+                            amount: 10_001_000,
+                            yield_gained: 1_000,
+                        }
+                        .into_val(&e),
+                    )
+                ]
+            );
+
             lottery_client.raffle();
+
+            assert_eq!(
+                vec![&e, last_event(&e, &lottery_client.address)],
+                vec![
+                    &e,
+                    (
+                        lottery_client.address.clone(),
+                        (Symbol::new(&e, "winner_drawn"), user.clone()).into_val(&e),
+                        WinnerDrawnEvent {
+                            round_id: 1,
+                            ticket_id: 1,
+                            prize: 1_000,
+                        }
+                        .into_val(&e),
+                    )
+                ]
+            );
         }
 
         #[test]
-        fn status_ended() {
+        fn emissions_claimed() {
             let e = Env::default();
             e.mock_all_auths();
-            e.ledger().with_mut(|li| {
-                li.sequence_number = 1;
-                li.min_persistent_entry_ttl = 10_000_000;
-                li.min_temp_entry_ttl = 1_000_000;
-                li.max_entry_ttl = 1_000_001;
-            });
             let TestEnv {
+                admin,
+                blend_client,
                 lottery_client,
-                user,
                 ..
             } = setup_test_env(&e);
 
-            let mut user_ticket = lottery_client.buy_ticket(&user);
+            blend_client.set_emissions(&500);
+            lottery_client.admin_claim_emissions();
+
+            assert_eq!(
+                vec![&e, last_event(&e, &lottery_client.address)],
+                vec![
+                    &e,
+                    (
+                        lottery_client.address.clone(),
+                        (Symbol::new(&e, "emissions_claimed"), admin.clone()).into_val(&e),
+                        500_i128.into_val(&e),
+                    )
+                ]
+            );
+        }
+    }
 
-            e.ledger().with_mut(|li| {
-                li.sequence_number = 100_000;
-            });
-            lottery_client.set_status(&LotteryStatus::YieldFarming);
-            e.ledger().with_mut(|li| {
-                li.sequence_number = 300_000;
-            });
-            lottery_client.set_status(&LotteryStatus::Ended);
-            let winner = lottery_client.raffle();
+    fn last_event(e: &Env, contract: &Address) -> (Address, soroban_sdk::Vec<Val>, Val) {
+        e.events()
+            .all()
+            .iter()
+            .filter(|event| event.0 == *contract)
+            .last()
+            .unwrap()
+    }
 
-            user_ticket.won = true;
+    mod mock_blend {
+        use soroban_sdk::{contract, contractimpl, contracttype, token, Address, Env, Map, Vec};
+
+        use super::blend;
+        use crate::storage::SCALAR_12;
+
+        #[derive(Clone)]
+        #[contracttype]
+        enum MockKey {
+            Reserves,
+            Supply(Address),
+            BRate(Address),
+            Emissions,
+            EmissionsToken,
+        }
+
+        // Minimal stand-in for the Blend pool: supplies and withdraws the
+        // underlying against b-tokens, rounding like Blend, and lets tests
+        // accrue interest on a reserve by raising its `b_rate`.
+        #[contract]
+        pub struct MockBlendPool;
+
+        #[contractimpl]
+        impl MockBlendPool {
+            pub fn set_reserve_list(e: Env, reserves: Vec<Address>) {
+                e.storage().instance().set(&MockKey::Reserves, &reserves);
+            }
+
+            pub fn get_reserve_list(e: Env) -> Vec<Address> {
+                e.storage()
+                    .instance()
+                    .get(&MockKey::Reserves)
+                    .unwrap_or(Vec::new(&e))
+            }
+
+            // The accrued amount has to be minted to the pool by the test.
+            // Rounding the rate up makes the position worth exactly
+            // `amount` more.
+            pub fn accrue(e: Env, asset: Address, amount: i128) {
+                let b_tokens = Self::supply(&e, &asset);
+                let value = Self::value(&e, &asset, b_tokens);
+                let b_rate = ((value + amount) * SCALAR_12 + b_tokens - 1) / b_tokens;
+                e.storage().instance().set(&MockKey::BRate(asset), &b_rate);
+            }
+
+            pub fn get_reserve(e: Env, asset: Address) -> blend::Reserve {
+                let index = Self::get_reserve_list(e.clone())
+                    .first_index_of(&asset)
+                    .unwrap_or(0);
+                blend::Reserve {
+                    asset: asset.clone(),
+                    config: blend::ReserveConfig {
+                        c_factor: 0,
+                        decimals: 7,
+                        enabled: true,
+                        index,
+                        l_factor: 0,
+                        max_util: 0,
+                        r_base: 0,
+                        r_one: 0,
+                        r_three: 0,
+                        r_two: 0,
+                        reactivity: 0,
+                        supply_cap: i128::MAX,
+                        util: 0,
+                    },
+                    data: blend::ReserveData {
+                        b_rate: Self::b_rate(&e, &asset),
+                        b_supply: Self::supply(&e, &asset),
+                        backstop_credit: 0,
+                        d_rate: SCALAR_12,
+                        d_supply: 0,
+                        ir_mod: 0,
+                        last_time: e.ledger().timestamp(),
+                    },
+                    scalar: 10_000_000,
+                }
+            }
+
+            pub fn get_positions(e: Env, _address: Address) -> blend::Positions {
+                let mut supply = Map::new(&e);
+                for (index, asset) in Self::get_reserve_list(e.clone()).iter().enumerate() {
+                    let b_tokens = Self::supply(&e, &asset);
+                    if b_tokens > 0 {
+                        supply.set(index as u32, b_tokens);
+                    }
+                }
+
+                blend::Positions {
+                    collateral: Map::new(&e),
+                    liabilities: Map::new(&e),
+                    supply,
+                }
+            }
+
+            pub fn submit(
+                e: Env,
+                from: Address,
+                spender: Address,
+                to: Address,
+                requests: Vec<blend::Request>,
+            ) -> blend::Positions {
+                for request in requests.iter() {
+                    let token_client = token::Client::new(&e, &request.address);
+                    let supply = Self::supply(&e, &request.address);
+                    let b_rate = Self::b_rate(&e, &request.address);
+                    let new_supply = match request.request_type {
+                        0 => {
+                            token_client.transfer(
+                                &spender,
+                                &e.current_contract_address(),
+                                &request.amount,
+                            );
+                            supply + request.amount * SCALAR_12 / b_rate
+                        }
+                        1 => {
+                            let value = Self::value(&e, &request.address, supply);
+                            let amount = request.amount.min(value);
+                            token_client.transfer(&e.current_contract_address(), &to, &amount);
+                            if amount == value {
+                                0
+                            } else {
+                                supply - (amount * SCALAR_12 + b_rate - 1) / b_rate
+                            }
+                        }
+                        _ => panic!("unsupported request type"),
+                    };
+                    e.storage()
+                        .instance()
+                        .set(&MockKey::Supply(request.address.clone()), &new_supply);
+                    // An empty reserve starts over at 1:1, which keeps the
+                    // amounts in later rounds exact
+                    if new_supply == 0 {
+                        e.storage()
+                            .instance()
+                            .remove(&MockKey::BRate(request.address));
+                    }
+                }
+
+                Self::get_positions(e, from)
+            }
+
+            pub fn set_emissions(e: Env, amount: i128) {
+                e.storage().instance().set(&MockKey::Emissions, &amount);
+            }
+
+            // With an emissions token set, the claimed amount has to be
+            // minted to the pool by the test.
+            pub fn set_emissions_token(e: Env, token: Address) {
+                e.storage().instance().set(&MockKey::EmissionsToken, &token);
+            }
+
+            pub fn claim(
+                e: Env,
+                _from: Address,
+                _reserve_token_ids: Vec<u32>,
+                to: Address,
+            ) -> i128 {
+                let amount: i128 = e.storage().instance().get(&MockKey::Emissions).unwrap_or(0);
+                let emissions_token: Option<Address> =
+                    e.storage().instance().get(&MockKey::EmissionsToken);
+                if let Some(emissions_token) = emissions_token {
+                    token::Client::new(&e, &emissions_token).transfer(
+                        &e.current_contract_address(),
+                        &to,
+                        &amount,
+                    );
+                }
+                amount
+            }
+        }
+
+        impl MockBlendPool {
+            fn supply(e: &Env, asset: &Address) -> i128 {
+                e.storage()
+                    .instance()
+                    .get(&MockKey::Supply(asset.clone()))
+                    .unwrap_or(0)
+            }
 
-            assert_eq!(user_ticket, winner)
+            fn b_rate(e: &Env, asset: &Address) -> i128 {
+                e.storage()
+                    .instance()
+                    .get(&MockKey::BRate(asset.clone()))
+                    .unwrap_or(SCALAR_12)
+            }
+
+            fn value(e: &Env, asset: &Address, b_tokens: i128) -> i128 {
+                b_tokens * Self::b_rate(e, asset) / SCALAR_12
+            }
+        }
+    }
+
+    mod mock_router {
+        use soroban_sdk::{contract, contractimpl, token, Address, Env};
+
+        // Swaps at a fixed rate of two output tokens per input token. The
+        // output has to be minted to the router by the test.
+        #[contract]
+        pub struct MockRouter;
+
+        #[contractimpl]
+        impl MockRouter {
+            pub fn swap(
+                e: Env,
+                from: Address,
+                token_in: Address,
+                token_out: Address,
+                amount_in: i128,
+                _min_amount_out: i128,
+            ) -> i128 {
+                token::Client::new(&e, &token_in).transfer(
+                    &from,
+                    &e.current_contract_address(),
+                    &amount_in,
+                );
+                let amount_out = amount_in * 2;
+                token::Client::new(&e, &token_out).transfer(
+                    &e.current_contract_address(),
+                    &from,
+                    &amount_out,
+                );
+                amount_out
+            }
         }
     }
 
@@ -850,9 +6395,23 @@ mod tests {
         xlm_token_client: TokenClient<'a>,
         xlm_address: Address,
         blend_address: Address,
+        blend_client: mock_blend::MockBlendPoolClient<'a>,
         lottery_client: NoLossLotteryClient<'a>,
     }
+    fn default_phase_durations() -> PhaseDurations {
+        PhaseDurations {
+            buyin_ledgers: 17_300,
+            farming_ledgers: 103_800,
+            ended_ledgers: 17_300,
+            reveal_ledgers: 8_650,
+        }
+    }
+
     fn setup_test_env(e: &Env) -> TestEnv<'_> {
+        setup_test_env_with_durations(e, default_phase_durations())
+    }
+
+    fn setup_test_env_with_durations(e: &Env, phase_durations: PhaseDurations) -> TestEnv<'_> {
         let admin = Address::generate(e);
         let user = Address::generate(e);
 
@@ -865,7 +6424,9 @@ mod tests {
         xlm_asset_client.mint(&admin, &20_000_000_i128);
         xlm_asset_client.mint(&user, &20_000_000_i128);
 
-        let blend_address = Address::generate(e);
+        let blend_address = e.register(mock_blend::MockBlendPool, ());
+        let blend_client = mock_blend::MockBlendPoolClient::new(e, &blend_address);
+        blend_client.set_reserve_list(&vec![e, xlm_address.clone()]);
 
         let lottery_address = e.register(
             NoLossLottery,
@@ -874,6 +6435,7 @@ mod tests {
                 xlm_address.clone(),
                 10_000_000_i128,
                 blend_address.clone(),
+                phase_durations,
             ),
         );
         let lottery_client = NoLossLotteryClient::new(e, &lottery_address);
@@ -885,7 +6447,56 @@ mod tests {
             xlm_token_client,
             xlm_address,
             blend_address,
+            blend_client,
             lottery_client,
         }
     }
+
+    fn set_ledger(e: &Env, sequence_number: u32) {
+        e.ledger().with_mut(|li| {
+            li.sequence_number = sequence_number;
+            li.min_persistent_entry_ttl = 10_000_000;
+            li.min_temp_entry_ttl = 1_000_000;
+            li.max_entry_ttl = 1_000_001;
+        });
+    }
+
+    // Mints `amount` to the mock Blend pool and accrues it as interest on the
+    // supplied XLM.
+    fn accrue_yield(test_env: &TestEnv, amount: i128) {
+        test_env
+            .xlm_asset_client
+            .mint(&test_env.blend_address, &amount);
+        test_env.blend_client.accrue(&test_env.xlm_address, &amount);
+    }
+
+    fn start_farming(e: &Env, test_env: &TestEnv, sequence_number: u32) {
+        set_ledger(e, sequence_number);
+        test_env
+            .lottery_client
+            .set_status(&LotteryStatus::YieldFarming);
+        test_env.lottery_client.blend_it(&test_env.admin);
+    }
+
+    fn end_farming(e: &Env, test_env: &TestEnv, sequence_number: u32) {
+        set_ledger(e, sequence_number);
+        test_env.lottery_client.set_status(&LotteryStatus::Ended);
+        test_env.lottery_client.withdraw_from_blend(&test_env.admin);
+    }
+
+    // Leaves the lottery farming with one ticket supplied to Blend.
+    fn supply_ticket(e: &Env, test_env: &TestEnv) {
+        set_ledger(e, 1);
+        test_env.lottery_client.buy_ticket(&test_env.user);
+        start_farming(e, test_env, 17_301);
+    }
+
+    // Farms `yield_amount` in a round whose BuyIn started at `start_ledger`,
+    // moving on as soon as the default timelocks allow, and leaves the
+    // lottery in Ended with the funds withdrawn.
+    fn farm_round(e: &Env, test_env: &TestEnv, start_ledger: u32, yield_amount: i128) {
+        start_farming(e, test_env, start_ledger + 17_300);
+        accrue_yield(test_env, yield_amount);
+        end_farming(e, test_env, start_ledger + 121_100);
+    }
 }
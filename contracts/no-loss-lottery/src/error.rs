@@ -25,4 +25,29 @@ pub enum LotteryError {
     BuyInStartedLedgerNotFound = 19,
     EndedStartedLedgerNotFound = 20,
     MinimumTimeLockNotEnded = 21,
+    AlreadyCommitted = 22,
+    CommitmentNotFound = 23,
+    AlreadyRevealed = 24,
+    InvalidReveal = 25,
+    RevealWindowOpen = 26,
+    PrizeTiersNotFound = 27,
+    InvalidPrizeTiers = 28,
+    RoundNotFound = 29,
+    NoTicketsInRound = 30,
+    PrizeBelowMinimum = 31,
+    InvalidAmount = 32,
+    PhaseDurationsNotFound = 33,
+    InvalidPhaseDurations = 34,
+    PendingPhaseDurationsNotFound = 35,
+    PendingUpgradeNotFound = 36,
+    PoolNotFound = 37,
+    PoolAlreadyExists = 38,
+    TooManyPools = 39,
+    InvalidProtocolFee = 40,
+    ProtocolFeeNotFound = 41,
+    InvalidEmissionsConfig = 42,
+    WrongEmissionsMode = 43,
+    SwapBelowMinimum = 44,
+    ReserveNotFound = 45,
+    Insolvent = 46,
 }
@@ -0,0 +1,298 @@
+use crate::storage::{
+    AdvanceStep, EmissionsConfig, LotteryStatus, PendingPhaseDurations, PendingUpgrade,
+    PhaseDurations, PrizeTier, ProtocolFee, Role,
+};
+use soroban_sdk::{contracttype, Address, BytesN, Env, Symbol, Vec};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[contracttype]
+pub struct TicketEvent {
+    pub ticket_id: u32,
+    pub amount: i128,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[contracttype]
+pub struct StatusChangedEvent {
+    pub old_status: LotteryStatus,
+    pub new_status: LotteryStatus,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[contracttype]
+pub struct WithdrawnFromBlendEvent {
+    pub amount: i128,
+    pub yield_gained: i128,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[contracttype]
+pub struct WinnerDrawnEvent {
+    pub round_id: u32,
+    pub ticket_id: u32,
+    pub prize: i128,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[contracttype]
+pub struct PrizeRolledOverEvent {
+    pub round_id: u32,
+    pub amount: i128,
+    pub jackpot: i128,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[contracttype]
+pub struct AdvancedEvent {
+    pub step: AdvanceStep,
+    pub bounty: i128,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[contracttype]
+pub struct EmissionsSwappedEvent {
+    pub amount_in: i128,
+    pub amount_out: i128,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[contracttype]
+pub struct EmissionsAwardedEvent {
+    pub round_id: u32,
+    pub ticket_id: u32,
+    pub amount: i128,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[contracttype]
+pub struct FeesWithdrawnEvent {
+    pub treasury: Address,
+    pub amount: i128,
+}
+
+pub fn ticket_bought(e: &Env, user: &Address, ticket_id: u32, amount: i128) {
+    e.events().publish(
+        (Symbol::new(e, "ticket_bought"), user.clone()),
+        TicketEvent { ticket_id, amount },
+    );
+}
+
+pub fn deposited(e: &Env, user: &Address, ticket_id: u32, amount: i128) {
+    e.events().publish(
+        (Symbol::new(e, "deposited"), user.clone()),
+        TicketEvent { ticket_id, amount },
+    );
+}
+
+pub fn ticket_redeemed(e: &Env, user: &Address, ticket_id: u32, amount: i128) {
+    e.events().publish(
+        (Symbol::new(e, "ticket_redeemed"), user.clone()),
+        TicketEvent { ticket_id, amount },
+    );
+}
+
+pub fn status_changed(e: &Env, old_status: &LotteryStatus, new_status: &LotteryStatus) {
+    e.events().publish(
+        (Symbol::new(e, "status_changed"),),
+        StatusChangedEvent {
+            old_status: old_status.clone(),
+            new_status: new_status.clone(),
+        },
+    );
+}
+
+pub fn deposited_to_blend(e: &Env, token: &Address, amount: i128) {
+    e.events().publish(
+        (Symbol::new(e, "deposited_to_blend"), token.clone()),
+        amount,
+    );
+}
+
+pub fn withdrawn_from_blend(e: &Env, token: &Address, amount: i128, yield_gained: i128) {
+    e.events().publish(
+        (Symbol::new(e, "withdrawn_from_blend"), token.clone()),
+        WithdrawnFromBlendEvent {
+            amount,
+            yield_gained,
+        },
+    );
+}
+
+pub fn winner_drawn(e: &Env, user: &Address, round_id: u32, ticket_id: u32, prize: i128) {
+    e.events().publish(
+        (Symbol::new(e, "winner_drawn"), user.clone()),
+        WinnerDrawnEvent {
+            round_id,
+            ticket_id,
+            prize,
+        },
+    );
+}
+
+pub fn prize_rolled_over(e: &Env, token: &Address, round_id: u32, amount: i128, jackpot: i128) {
+    e.events().publish(
+        (Symbol::new(e, "prize_rolled_over"), token.clone()),
+        PrizeRolledOverEvent {
+            round_id,
+            amount,
+            jackpot,
+        },
+    );
+}
+
+pub fn min_prize_updated(e: &Env, token: &Address, amount: i128) {
+    e.events()
+        .publish((Symbol::new(e, "min_prize_updated"), token.clone()), amount);
+}
+
+pub fn pool_added(e: &Env, token: &Address, ticket_amount: i128) {
+    e.events()
+        .publish((Symbol::new(e, "pool_added"), token.clone()), ticket_amount);
+}
+
+pub fn reserve_index_updated(e: &Env, token: &Address, index: u32) {
+    e.events().publish(
+        (Symbol::new(e, "reserve_index_updated"), token.clone()),
+        index,
+    );
+}
+
+pub fn emissions_claimed(e: &Env, to: &Address, amount: i128) {
+    e.events()
+        .publish((Symbol::new(e, "emissions_claimed"), to.clone()), amount);
+}
+
+pub fn emissions_config_updated(e: &Env, config: &EmissionsConfig) {
+    e.events().publish(
+        (Symbol::new(e, "emissions_config_updated"),),
+        config.clone(),
+    );
+}
+
+pub fn emissions_swapped(e: &Env, token: &Address, amount_in: i128, amount_out: i128) {
+    e.events().publish(
+        (Symbol::new(e, "emissions_swapped"), token.clone()),
+        EmissionsSwappedEvent {
+            amount_in,
+            amount_out,
+        },
+    );
+}
+
+pub fn emissions_awarded(e: &Env, user: &Address, round_id: u32, ticket_id: u32, amount: i128) {
+    e.events().publish(
+        (Symbol::new(e, "emissions_awarded"), user.clone()),
+        EmissionsAwardedEvent {
+            round_id,
+            ticket_id,
+            amount,
+        },
+    );
+}
+
+pub fn committed(e: &Env, committer: &Address, commitment: &BytesN<32>) {
+    e.events().publish(
+        (Symbol::new(e, "committed"), committer.clone()),
+        commitment.clone(),
+    );
+}
+
+pub fn revealed(e: &Env, committer: &Address, secret: &BytesN<32>) {
+    e.events().publish(
+        (Symbol::new(e, "revealed"), committer.clone()),
+        secret.clone(),
+    );
+}
+
+pub fn prize_tiers_updated(e: &Env, tiers: &Vec<PrizeTier>) {
+    e.events()
+        .publish((Symbol::new(e, "prize_tiers_updated"),), tiers.clone());
+}
+
+pub fn role_granted(e: &Env, role: &Role, account: &Address) {
+    e.events().publish(
+        (Symbol::new(e, "role_granted"), account.clone()),
+        role.clone(),
+    );
+}
+
+pub fn role_revoked(e: &Env, role: &Role, account: &Address) {
+    e.events().publish(
+        (Symbol::new(e, "role_revoked"), account.clone()),
+        role.clone(),
+    );
+}
+
+pub fn phase_durations_proposed(e: &Env, pending: &PendingPhaseDurations) {
+    e.events().publish(
+        (Symbol::new(e, "phase_durations_proposed"),),
+        pending.clone(),
+    );
+}
+
+pub fn phase_durations_applied(e: &Env, durations: &PhaseDurations) {
+    e.events().publish(
+        (Symbol::new(e, "phase_durations_applied"),),
+        durations.clone(),
+    );
+}
+
+pub fn phase_durations_cancelled(e: &Env) {
+    e.events()
+        .publish((Symbol::new(e, "phase_durations_cancelled"),), ());
+}
+
+pub fn advanced(e: &Env, caller: &Address, step: &AdvanceStep, bounty: i128) {
+    e.events().publish(
+        (Symbol::new(e, "advanced"), caller.clone()),
+        AdvancedEvent {
+            step: step.clone(),
+            bounty,
+        },
+    );
+}
+
+pub fn keeper_bounty_updated(e: &Env, amount: i128) {
+    e.events()
+        .publish((Symbol::new(e, "keeper_bounty_updated"),), amount);
+}
+
+pub fn rolling_mode_updated(e: &Env, enabled: bool) {
+    e.events()
+        .publish((Symbol::new(e, "rolling_mode_updated"),), enabled);
+}
+
+pub fn protocol_fee_updated(e: &Env, fee: &ProtocolFee) {
+    e.events()
+        .publish((Symbol::new(e, "protocol_fee_updated"),), fee.clone());
+}
+
+pub fn fees_collected(e: &Env, token: &Address, amount: i128) {
+    e.events()
+        .publish((Symbol::new(e, "fees_collected"), token.clone()), amount);
+}
+
+pub fn fees_withdrawn(e: &Env, token: &Address, treasury: &Address, amount: i128) {
+    e.events().publish(
+        (Symbol::new(e, "fees_withdrawn"), token.clone()),
+        FeesWithdrawnEvent {
+            treasury: treasury.clone(),
+            amount,
+        },
+    );
+}
+
+pub fn upgrade_scheduled(e: &Env, pending: &PendingUpgrade) {
+    e.events()
+        .publish((Symbol::new(e, "upgrade_scheduled"),), pending.clone());
+}
+
+pub fn upgrade_cancelled(e: &Env) {
+    e.events()
+        .publish((Symbol::new(e, "upgrade_cancelled"),), ());
+}
+
+pub fn upgraded(e: &Env, wasm_hash: &BytesN<32>) {
+    e.events()
+        .publish((Symbol::new(e, "upgraded"),), wasm_hash.clone());
+}
@@ -3,5 +3,6 @@
 
 mod contract;
 mod error;
+mod events;
 mod storage;
 mod util;
@@ -1,10 +1,24 @@
 use crate::error::LotteryError;
-use soroban_sdk::{contracttype, Address, Bytes, Env, Vec};
-
-const DAY_IN_LEDGERS: u32 = 17300;
-pub const MIN_YIELD_TIME_IN_LEDGERS: u32 = DAY_IN_LEDGERS * 6;
-pub const MIN_BUYIN_TIME_IN_LEDGERS: u32 = DAY_IN_LEDGERS;
-pub const MIN_ENDED_TIME_IN_LEDGERS: u32 = DAY_IN_LEDGERS;
+use soroban_sdk::{contracttype, Address, Bytes, BytesN, Env, IntoVal, TryFromVal, Val, Vec};
+
+pub const MAX_PRIZE_TIERS: u32 = 10;
+pub const BPS_DENOMINATOR: u32 = 10_000;
+/// Fixed point scale of Blend's `b_rate`.
+pub const SCALAR_12: i128 = 1_000_000_000_000;
+pub const MAX_ROUNDS_PAGE_SIZE: u32 = 50;
+pub const MAX_POOLS: u32 = 10;
+/// Hard cap on the protocol fee, 20% of the yield.
+pub const MAX_PROTOCOL_FEE_BPS: u32 = 2_000;
+pub const SCHEMA_VERSION: u32 = 1;
+pub const TICKET_NAME: &str = "No Loss Lottery Ticket";
+pub const TICKET_SYMBOL: &str = "NLLT";
+pub const TICKET_BASE_URI: &str = "https://teolhyn.github.io/no-loss-lottery/tickets/";
+
+pub const DAY_IN_LEDGERS: u32 = 17_300;
+/// Entries are extended to live this many ledgers whenever they are read or
+/// written, so anything touched once a month never gets archived.
+pub const TTL_EXTEND_AMOUNT: u32 = 30 * DAY_IN_LEDGERS;
+pub const TTL_THRESHOLD: u32 = TTL_EXTEND_AMOUNT - DAY_IN_LEDGERS;
 
 #[derive(PartialEq, Eq, Debug, Clone)]
 #[contracttype]
@@ -14,15 +28,48 @@ pub enum LotteryStatus {
     Ended,
 }
 
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[contracttype]
+pub enum Role {
+    Operator,
+    Keeper,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[contracttype]
+pub enum AdvanceStep {
+    StartFarming,
+    DepositToBlend,
+    EndFarming,
+    WithdrawFromBlend,
+    Raffle,
+    StartBuyIn,
+}
+
 #[contracttype]
 pub struct LotteryState {
     pub status: LotteryStatus,
     pub no_participants: u32,
-    pub amount_of_yield: i128,
     pub token: Address,
     pub in_blender: bool,
 }
 
+/// Tickets of one token, with their own Blend reserve position and prize.
+/// `principal` and `unclaimed_prizes` are owed to ticket holders,
+/// `amount_of_yield` and `jackpot` to the next winners.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[contracttype]
+pub struct Pool {
+    pub token: Address,
+    pub ticket_amount: i128,
+    pub min_prize: i128,
+    pub principal: i128,
+    pub unclaimed_prizes: i128,
+    pub sent_balance: i128,
+    pub amount_of_yield: i128,
+    pub jackpot: i128,
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 #[contracttype]
 pub struct Ticket {
@@ -31,35 +78,213 @@ pub struct Ticket {
     pub token: Address,
     pub amount: i128,
     pub won: bool,
+    pub round_id: u32,
+    pub principal: i128,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[contracttype]
+pub struct TicketMetadata {
+    pub round_id: u32,
+    pub principal: i128,
+    pub won: bool,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[contracttype]
+pub struct TicketOdds {
+    pub weight: i128,
+    pub total_weight: i128,
+    pub ledger: u32,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[contracttype]
+pub struct PhaseDurations {
+    pub buyin_ledgers: u32,
+    pub farming_ledgers: u32,
+    pub ended_ledgers: u32,
+    pub reveal_ledgers: u32,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[contracttype]
+pub struct PendingPhaseDurations {
+    pub durations: PhaseDurations,
+    pub unlock_ledger: u32,
+}
+
+/// A committer's hash of their secret for one round.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[contracttype]
+pub struct Commitment {
+    pub hash: BytesN<32>,
+    pub revealed: bool,
+}
+
+/// Randomness contributed to one round. `mix` is the XOR of the revealed
+/// secrets, so it does not depend on the order they were revealed in.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[contracttype]
+pub struct RoundRandomness {
+    pub commitments: u32,
+    pub reveals: u32,
+    pub mix: BytesN<32>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[contracttype]
+pub struct PendingUpgrade {
+    pub wasm_hash: BytesN<32>,
+    pub unlock_ledger: u32,
+}
+
+/// Share of every pool's yield that accrues to the treasury.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[contracttype]
+pub struct ProtocolFee {
+    pub fee_bps: u32,
+    pub treasury: Address,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[contracttype]
+pub enum EmissionsMode {
+    /// Emissions are sent to the admin.
+    Admin,
+    /// Emissions are swapped into the first pool's token for its prize.
+    Swap,
+    /// Emissions are paid to the raffle's first winner as a second prize.
+    Prize,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[contracttype]
+pub struct EmissionsConfig {
+    pub mode: EmissionsMode,
+    pub blnd_token: Address,
+    pub router: Option<Address>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[contracttype]
+pub struct PrizeTier {
+    pub share_bps: u32,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[contracttype]
+pub struct RoundRecord {
+    pub round_id: u32,
+    pub buyin_start_ledger: u32,
+    pub buyin_end_ledger: u32,
+    pub farming_start_ledger: u32,
+    pub farming_end_ledger: u32,
+    pub ended_start_ledger: u32,
+    pub ended_end_ledger: u32,
+    pub no_participants: u32,
+    pub no_tickets: u32,
+    pub pools: Vec<PoolRecord>,
+    pub emissions: i128,
+    pub winning_ticket_ids: Vec<u32>,
+    pub seed: Bytes,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[contracttype]
+pub struct PoolRecord {
+    pub token: Address,
+    pub total_deposited: i128,
+    pub amount_of_yield: i128,
+    pub prize_pot: i128,
 }
 
 #[derive(Clone)]
 #[contracttype]
-enum Key {
+pub(crate) enum Key {
     Admin,
     LotteryStatus,
     Currency,
-    TokenAmount,
     LotteryState,
     Ticket(u32),
     TicketCounter,
-    UserTickets(Address),
-    SentBalance,
     Blender,
-    Ids,
+    Pools,
+    Pool(Address),
+    PoolTicketCount(Address),
+    PoolTicket(Address, u32),
+    ActiveTicketIndex(u32),
+    PoolTicketWeight(Address, u32),
+    PoolEntryWeight(Address, u32),
+    TicketEntry(u32),
+    PoolPosition(Address, Address),
     WinnerSelected,
     Seed,
     FarmingStartedLedger,
     BuyInStartedLedger,
     EndedStartedLedger,
+    RoleMembers(Role),
+    Commitment(u32, Address),
+    Randomness(u32),
+    CommittedTicket(u32, u32),
+    PrizeTiers,
+    CurrentRound,
+    Round(u32),
+    PhaseDurations,
+    PendingPhaseDurations,
+    KeeperBounty,
+    RollingMode,
+    SchemaVersion,
+    PendingUpgrade,
+    ProtocolFee,
+    AccruedFees(Address),
+    EmissionsConfig,
+    ReserveIndex(Address),
+}
+
+// Every access goes through these so reads and writes also bump the TTL.
+// Instance entries share one TTL with the contract instance and code.
+fn get_instance<V: TryFromVal<Env, Val>>(e: &Env, key: &Key) -> Option<V> {
+    extend_instance(e);
+    e.storage().instance().get(key)
+}
+
+fn set_instance<V: IntoVal<Env, Val>>(e: &Env, key: &Key, value: &V) {
+    e.storage().instance().set(key, value);
+    extend_instance(e);
+}
+
+fn get_persistent<V: TryFromVal<Env, Val>>(e: &Env, key: &Key) -> Option<V> {
+    let value = e.storage().persistent().get(key);
+    if value.is_some() {
+        extend_persistent(e, key);
+    }
+    value
+}
+
+fn set_persistent<V: IntoVal<Env, Val>>(e: &Env, key: &Key, value: &V) {
+    e.storage().persistent().set(key, value);
+    extend_persistent(e, key);
+}
+
+fn extend_persistent(e: &Env, key: &Key) {
+    e.storage()
+        .persistent()
+        .extend_ttl(key, TTL_THRESHOLD, TTL_EXTEND_AMOUNT);
+}
+
+pub fn extend_instance(e: &Env) {
+    e.storage()
+        .instance()
+        .extend_ttl(TTL_THRESHOLD, TTL_EXTEND_AMOUNT);
 }
 
 pub fn write_admin(e: &Env, admin: &Address) {
-    e.storage().instance().set(&Key::Admin, admin);
+    set_instance(e, &Key::Admin, admin);
 }
 
 pub fn read_admin(e: &Env) -> Option<Address> {
-    e.storage().instance().get(&Key::Admin)
+    get_instance(e, &Key::Admin)
 }
 
 pub fn is_admin(e: &Env, address: &Address) -> bool {
@@ -71,232 +296,484 @@ pub fn is_admin(e: &Env, address: &Address) -> bool {
 }
 
 pub fn write_lottery_status(e: &Env, status: &LotteryStatus) {
-    e.storage().instance().set(&Key::LotteryStatus, status);
+    set_instance(e, &Key::LotteryStatus, status);
 
     let current_ledger = e.ledger().sequence();
 
     match status {
-        LotteryStatus::BuyIn => e
-            .storage()
-            .instance()
-            .set(&Key::BuyInStartedLedger, &current_ledger),
-        LotteryStatus::YieldFarming => e
-            .storage()
-            .instance()
-            .set(&Key::FarmingStartedLedger, &current_ledger),
-        LotteryStatus::Ended => e
-            .storage()
-            .instance()
-            .set(&Key::EndedStartedLedger, &current_ledger),
+        LotteryStatus::BuyIn => set_instance(e, &Key::BuyInStartedLedger, &current_ledger),
+        LotteryStatus::YieldFarming => set_instance(e, &Key::FarmingStartedLedger, &current_ledger),
+        LotteryStatus::Ended => set_instance(e, &Key::EndedStartedLedger, &current_ledger),
     }
 }
 
 pub fn read_lottery_status(e: &Env) -> Result<LotteryStatus, LotteryError> {
-    e.storage()
-        .instance()
-        .get(&Key::LotteryStatus)
-        .ok_or(LotteryError::LotteryStatusNotFound)
+    get_instance(e, &Key::LotteryStatus).ok_or(LotteryError::LotteryStatusNotFound)
 }
 
+/// Token of the first pool, which `buy_ticket` and `deposit` use.
 pub fn write_currency(e: &Env, token: &Address) {
-    e.storage().persistent().set(&Key::Currency, token);
+    set_persistent(e, &Key::Currency, token);
 }
 
 pub fn read_currency(e: &Env) -> Result<Address, LotteryError> {
-    e.storage()
-        .persistent()
-        .get(&Key::Currency)
-        .ok_or(LotteryError::LotteryCurrencyNotFound)
+    get_persistent(e, &Key::Currency).ok_or(LotteryError::LotteryCurrencyNotFound)
 }
 
-pub fn write_token_amount(e: &Env, amount: &i128) {
-    e.storage().persistent().set(&Key::TokenAmount, amount);
+pub fn write_lottery_state(e: &Env, state: &LotteryState) {
+    set_persistent(e, &Key::LotteryState, state);
 }
 
-pub fn read_token_amount(e: &Env) -> Result<i128, LotteryError> {
-    e.storage()
-        .persistent()
-        .get(&Key::TokenAmount)
-        .ok_or(LotteryError::TokenAmountNotFound)
+pub fn read_lottery_state(e: &Env) -> Result<LotteryState, LotteryError> {
+    get_persistent(e, &Key::LotteryState).ok_or(LotteryError::LotteryStateNotFound)
 }
 
-pub fn write_lottery_state(e: &Env, state: &LotteryState) {
-    e.storage().persistent().set(&Key::LotteryState, state);
+pub fn write_pool_tokens(e: &Env, tokens: &Vec<Address>) {
+    set_instance(e, &Key::Pools, tokens);
 }
 
-pub fn read_lottery_state(e: &Env) -> Result<LotteryState, LotteryError> {
-    e.storage()
-        .persistent()
-        .get(&Key::LotteryState)
-        .ok_or(LotteryError::LotteryStateNotFound)
+pub fn read_pool_tokens(e: &Env) -> Vec<Address> {
+    get_instance(e, &Key::Pools).unwrap_or(Vec::new(e))
+}
+
+pub fn write_pool(e: &Env, pool: &Pool) {
+    set_persistent(e, &Key::Pool(pool.token.clone()), pool);
+}
+
+pub fn read_pool(e: &Env, token: &Address) -> Result<Pool, LotteryError> {
+    get_persistent(e, &Key::Pool(token.clone())).ok_or(LotteryError::PoolNotFound)
 }
 
 pub fn read_ticket(e: &Env, id: u32) -> Result<Ticket, LotteryError> {
-    e.storage()
-        .persistent()
-        .get(&Key::Ticket(id))
-        .ok_or(LotteryError::TicketNotFound)
+    get_persistent(e, &Key::Ticket(id)).ok_or(LotteryError::TicketNotFound)
 }
 
 pub fn write_ticket(e: &Env, ticket: &Ticket) {
-    e.storage()
-        .persistent()
-        .set(&Key::Ticket(ticket.id), ticket);
+    set_persistent(e, &Key::Ticket(ticket.id), ticket);
+    let index = add_active_ticket(e, &ticket.token, ticket.id);
+    append_ticket_weight(e, &ticket.token, index, ticket.principal);
+    let entry = ticket.principal * e.ledger().sequence() as i128;
+    append_ticket_entry(e, &ticket.token, index, ticket.id, entry);
+}
 
-    // Add ticket ID to active IDs list
-    let mut ids: Vec<u32> = e
-        .storage()
-        .persistent()
-        .get(&Key::Ids)
-        .unwrap_or(Vec::new(e));
-    ids.push_back(ticket.id);
-    e.storage().persistent().set(&Key::Ids, &ids);
+pub fn update_ticket(e: &Env, ticket: &Ticket) {
+    set_persistent(e, &Key::Ticket(ticket.id), ticket);
 }
 
 pub fn get_and_increment_ticket_counter(e: &Env) -> u32 {
-    let current: u32 = e
-        .storage()
-        .persistent()
-        .get(&Key::TicketCounter)
-        .unwrap_or(0);
+    let current: u32 = get_persistent(e, &Key::TicketCounter).unwrap_or(0);
     let next = current + 1;
-    e.storage().persistent().set(&Key::TicketCounter, &next);
+    set_persistent(e, &Key::TicketCounter, &next);
     next
 }
 
-pub fn remove_ticket(e: &Env, ticket: Ticket) {
+pub fn remove_ticket(e: &Env, ticket: Ticket) -> Result<(), LotteryError> {
     e.storage().persistent().remove(&Key::Ticket(ticket.id));
-
-    // Remove ticket ID from active IDs list
-    let ids: Vec<u32> = e
-        .storage()
+    remove_active_ticket(e, &ticket)
+}
+
+// Every pool keeps its active ticket ids densely packed at positions
+// 0..count, each in its own entry, so adding and removing an id touches a
+// constant number of entries however many tickets there are.
+pub fn add_active_ticket(e: &Env, token: &Address, ticket_id: u32) -> u32 {
+    let count = read_active_ticket_count(e, token);
+    set_persistent(e, &Key::PoolTicket(token.clone(), count), &ticket_id);
+    set_persistent(e, &Key::ActiveTicketIndex(ticket_id), &count);
+    set_instance(e, &Key::PoolTicketCount(token.clone()), &(count + 1));
+    count
+}
+
+fn remove_active_ticket(e: &Env, ticket: &Ticket) -> Result<(), LotteryError> {
+    let token = &ticket.token;
+    let index = read_active_ticket_index(e, ticket.id)?;
+    let count = read_active_ticket_count(e, token);
+    let last = count - 1;
+    let entry = read_ticket_entry(e, ticket.id);
+
+    // Swap the last id into the freed position and pop it
+    if index != last {
+        let last_id = read_active_ticket(e, token, last)?;
+        let last_weight = read_ticket(e, last_id)?.principal;
+        add_node_at(
+            e,
+            |node| Key::PoolTicketWeight(token.clone(), node),
+            count,
+            index,
+            last_weight - ticket.principal,
+        );
+        add_node_at(
+            e,
+            |node| Key::PoolEntryWeight(token.clone(), node),
+            count,
+            index,
+            read_ticket_entry(e, last_id) - entry,
+        );
+        set_persistent(e, &Key::PoolTicket(token.clone(), index), &last_id);
+        set_persistent(e, &Key::ActiveTicketIndex(last_id), &index);
+    }
+    // The last nodes only cover ranges ending at the popped position
+    e.storage()
         .persistent()
-        .get(&Key::Ids)
-        .unwrap_or(Vec::new(e));
-
-    let mut new_ids = Vec::new(e);
-    for id in ids.iter() {
-        if id != ticket.id {
-            new_ids.push_back(id);
+        .remove(&Key::PoolTicketWeight(token.clone(), count));
+    e.storage()
+        .persistent()
+        .remove(&Key::PoolEntryWeight(token.clone(), count));
+    e.storage()
+        .persistent()
+        .remove(&Key::PoolTicket(token.clone(), last));
+    e.storage()
+        .persistent()
+        .remove(&Key::ActiveTicketIndex(ticket.id));
+    e.storage()
+        .persistent()
+        .remove(&Key::TicketEntry(ticket.id));
+    set_instance(e, &Key::PoolTicketCount(token.clone()), &last);
+    Ok(())
+}
+
+pub fn read_active_ticket_count(e: &Env, token: &Address) -> u32 {
+    get_instance(e, &Key::PoolTicketCount(token.clone())).unwrap_or(0)
+}
+
+pub fn read_active_ticket(e: &Env, token: &Address, index: u32) -> Result<u32, LotteryError> {
+    get_persistent(e, &Key::PoolTicket(token.clone(), index)).ok_or(LotteryError::IdsNotFound)
+}
+
+fn read_active_ticket_index(e: &Env, ticket_id: u32) -> Result<u32, LotteryError> {
+    get_persistent(e, &Key::ActiveTicketIndex(ticket_id)).ok_or(LotteryError::IdsNotFound)
+}
+
+// Ticket weights form Fenwick trees over each pool's active ticket index:
+// node `n` (1-based) holds the sum over the `n & n.wrapping_neg()` tickets
+// ending at position `n - 1`. `PoolTicketWeight` sums the principal and
+// `PoolEntryWeight` the principal times the ledger it was deposited in, so
+// the time-weighted balance up to any ledger follows from the two. Prefix
+// sums, updates and the weighted draw all touch O(log n) entries.
+pub fn read_ticket_weight_node(e: &Env, token: &Address, node: u32) -> i128 {
+    get_persistent(e, &Key::PoolTicketWeight(token.clone(), node)).unwrap_or(0)
+}
+
+pub fn read_ticket_entry_node(e: &Env, token: &Address, node: u32) -> i128 {
+    get_persistent(e, &Key::PoolEntryWeight(token.clone(), node)).unwrap_or(0)
+}
+
+/// Sum of every deposit into the ticket times the ledger it was made in.
+pub fn read_ticket_entry(e: &Env, ticket_id: u32) -> i128 {
+    get_persistent(e, &Key::TicketEntry(ticket_id)).unwrap_or(0)
+}
+
+/// Sets the principal of the ticket appended at `index` of the pool.
+pub fn append_ticket_weight(e: &Env, token: &Address, index: u32, principal: i128) {
+    append_node(
+        e,
+        |node| Key::PoolTicketWeight(token.clone(), node),
+        index,
+        principal,
+    );
+}
+
+/// Sets the ledger-weighted principal of the ticket appended at `index` of
+/// the pool.
+pub fn append_ticket_entry(e: &Env, token: &Address, index: u32, ticket_id: u32, entry: i128) {
+    set_persistent(e, &Key::TicketEntry(ticket_id), &entry);
+    append_node(
+        e,
+        |node| Key::PoolEntryWeight(token.clone(), node),
+        index,
+        entry,
+    );
+}
+
+/// Adds a deposit of `amount` made in the current ledger to the ticket.
+pub fn add_ticket_weight(e: &Env, ticket: &Ticket, amount: i128) -> Result<(), LotteryError> {
+    let token = &ticket.token;
+    let index = read_active_ticket_index(e, ticket.id)?;
+    let count = read_active_ticket_count(e, token);
+    let entry = amount * e.ledger().sequence() as i128;
+    add_node_at(
+        e,
+        |node| Key::PoolTicketWeight(token.clone(), node),
+        count,
+        index,
+        amount,
+    );
+    add_node_at(
+        e,
+        |node| Key::PoolEntryWeight(token.clone(), node),
+        count,
+        index,
+        entry,
+    );
+    set_persistent(
+        e,
+        &Key::TicketEntry(ticket.id),
+        &(read_ticket_entry(e, ticket.id) + entry),
+    );
+    Ok(())
+}
+
+/// Extends every entry that drawing or redeeming `ticket` reads besides the
+/// ticket itself: its index entries, its entry weight and the tree nodes on
+/// its update path, on the prefix sum before it and on the pool total.
+pub fn extend_ticket_entries(e: &Env, ticket: &Ticket) -> Result<(), LotteryError> {
+    let token = &ticket.token;
+    let index = read_active_ticket_index(e, ticket.id)?;
+    read_active_ticket(e, token, index)?;
+    read_ticket_entry(e, ticket.id);
+
+    let count = read_active_ticket_count(e, token);
+    let extend_node = |node: u32| {
+        read_ticket_weight_node(e, token, node);
+        read_ticket_entry_node(e, token, node);
+    };
+    let mut node = index + 1;
+    while node <= count {
+        extend_node(node);
+        node += node & node.wrapping_neg();
+    }
+    for mut node in [index, count] {
+        while node > 0 {
+            extend_node(node);
+            node -= node & node.wrapping_neg();
         }
     }
-    e.storage().persistent().set(&Key::Ids, &new_ids);
+    Ok(())
 }
 
-pub fn add_ticket_to_user(e: &Env, user: &Address, ticket_id: u32) {
-    let mut tickets: soroban_sdk::Vec<u32> = e
-        .storage()
-        .persistent()
-        .get(&Key::UserTickets(user.clone()))
-        .unwrap_or(soroban_sdk::Vec::new(e));
-
-    tickets.push_back(ticket_id);
-    e.storage()
-        .persistent()
-        .set(&Key::UserTickets(user.clone()), &tickets);
+fn read_node(e: &Env, node_key: &impl Fn(u32) -> Key, node: u32) -> i128 {
+    get_persistent(e, &node_key(node)).unwrap_or(0)
 }
 
-pub fn get_user_tickets(e: &Env, user: &Address) -> soroban_sdk::Vec<u32> {
-    e.storage()
-        .persistent()
-        .get(&Key::UserTickets(user.clone()))
-        .unwrap_or(soroban_sdk::Vec::new(e))
+// The new node also covers the nodes of the tickets before it in its range,
+// which are its children `node - 1`, `node - 2`, `node - 4`, ...
+fn append_node(e: &Env, node_key: impl Fn(u32) -> Key, index: u32, value: i128) {
+    let node = index + 1;
+    let range = node & node.wrapping_neg();
+    let mut sum = value;
+    let mut step = 1;
+    while step < range {
+        sum += read_node(e, &node_key, node - step);
+        step <<= 1;
+    }
+    set_persistent(e, &node_key(node), &sum);
 }
 
-pub fn remove_ticket_from_user(e: &Env, user: &Address, ticket_id: u32) {
-    let tickets: soroban_sdk::Vec<u32> = e
-        .storage()
-        .persistent()
-        .get(&Key::UserTickets(user.clone()))
-        .unwrap_or(soroban_sdk::Vec::new(e));
-
-    // Find and remove the ticket_id
-    let mut new_tickets = soroban_sdk::Vec::new(e);
-    for id in tickets.iter() {
-        if id != ticket_id {
-            new_tickets.push_back(id);
-        }
+fn add_node_at(e: &Env, node_key: impl Fn(u32) -> Key, count: u32, index: u32, delta: i128) {
+    let mut node = index + 1;
+    while node <= count {
+        let value = read_node(e, &node_key, node);
+        set_persistent(e, &node_key(node), &(value + delta));
+        node += node & node.wrapping_neg();
     }
+}
 
-    e.storage()
-        .persistent()
-        .set(&Key::UserTickets(user.clone()), &new_tickets);
+pub fn write_position(e: &Env, user: &Address, token: &Address, ticket_id: u32) {
+    set_persistent(
+        e,
+        &Key::PoolPosition(user.clone(), token.clone()),
+        &ticket_id,
+    );
 }
 
-pub fn write_sent_balance(e: &Env, sent_amount: &i128) {
-    e.storage().persistent().set(&Key::SentBalance, sent_amount);
+pub fn read_position(e: &Env, user: &Address, token: &Address) -> Option<u32> {
+    get_persistent(e, &Key::PoolPosition(user.clone(), token.clone()))
 }
 
-pub fn read_sent_balance(e: &Env) -> Result<i128, LotteryError> {
+pub fn remove_position(e: &Env, user: &Address, token: &Address) {
     e.storage()
         .persistent()
-        .get(&Key::SentBalance)
-        .ok_or(LotteryError::SentToBlendNotFound)
+        .remove(&Key::PoolPosition(user.clone(), token.clone()));
 }
 
 pub fn write_blend_address(e: &Env, blend_address: &Address) {
-    e.storage().instance().set(&Key::Blender, &blend_address);
+    set_instance(e, &Key::Blender, &blend_address);
 }
 
 pub fn read_blend_address(e: &Env) -> Result<Address, LotteryError> {
-    e.storage()
-        .instance()
-        .get(&Key::Blender)
-        .ok_or(LotteryError::BlenderNotFound)
-}
-
-pub fn read_ids(e: &Env) -> Result<Vec<u32>, LotteryError> {
-    e.storage()
-        .persistent()
-        .get(&Key::Ids)
-        .ok_or(LotteryError::IdsNotFound)
+    get_instance(e, &Key::Blender).ok_or(LotteryError::BlenderNotFound)
 }
 
 pub fn write_winner_selected(e: &Env, value: bool) {
-    e.storage().instance().set(&Key::WinnerSelected, &value);
+    set_instance(e, &Key::WinnerSelected, &value);
 }
 
 pub fn read_winner_selected(e: &Env) -> Result<bool, LotteryError> {
-    e.storage()
-        .instance()
-        .get(&Key::WinnerSelected)
-        .ok_or(LotteryError::WinnerSelectedNotFound)
+    get_instance(e, &Key::WinnerSelected).ok_or(LotteryError::WinnerSelectedNotFound)
 }
 
 pub fn write_seed(e: &Env, seed: &Bytes) {
-    e.storage().instance().set(&Key::Seed, seed);
+    set_instance(e, &Key::Seed, seed);
 }
 
 pub fn read_seed(e: &Env) -> Result<Bytes, LotteryError> {
-    e.storage()
-        .instance()
-        .get(&Key::Seed)
-        .ok_or(LotteryError::SeedNotFound)
+    get_instance(e, &Key::Seed).ok_or(LotteryError::SeedNotFound)
 }
 
 pub fn write_farming_started_ledger(e: &Env, ledger: u32) {
-    e.storage()
-        .instance()
-        .set(&Key::FarmingStartedLedger, &ledger);
+    set_instance(e, &Key::FarmingStartedLedger, &ledger);
 }
 
 pub fn read_farming_started_ledger(e: &Env) -> Result<u32, LotteryError> {
-    e.storage()
-        .instance()
-        .get(&Key::FarmingStartedLedger)
-        .ok_or(LotteryError::FarmingStartedLedgerNotFound)
+    get_instance(e, &Key::FarmingStartedLedger).ok_or(LotteryError::FarmingStartedLedgerNotFound)
 }
 
 pub fn read_buyin_started_ledger(e: &Env) -> Result<u32, LotteryError> {
-    e.storage()
-        .instance()
-        .get(&Key::BuyInStartedLedger)
-        .ok_or(LotteryError::BuyInStartedLedgerNotFound)
+    get_instance(e, &Key::BuyInStartedLedger).ok_or(LotteryError::BuyInStartedLedgerNotFound)
 }
 
 pub fn read_ended_started_ledger(e: &Env) -> Result<u32, LotteryError> {
-    e.storage()
-        .instance()
-        .get(&Key::EndedStartedLedger)
-        .ok_or(LotteryError::EndedStartedLedgerNotFound)
+    get_instance(e, &Key::EndedStartedLedger).ok_or(LotteryError::EndedStartedLedgerNotFound)
+}
+
+pub fn write_role_members(e: &Env, role: &Role, members: &Vec<Address>) {
+    set_instance(e, &Key::RoleMembers(role.clone()), members);
+}
+
+pub fn read_role_members(e: &Env, role: &Role) -> Vec<Address> {
+    get_instance(e, &Key::RoleMembers(role.clone())).unwrap_or(Vec::new(e))
+}
+
+pub fn has_role(e: &Env, role: &Role, address: &Address) -> bool {
+    read_role_members(e, role).contains(address)
+}
+
+pub fn write_commitment(e: &Env, round_id: u32, committer: &Address, commitment: &Commitment) {
+    set_persistent(e, &Key::Commitment(round_id, committer.clone()), commitment);
+}
+
+pub fn read_commitment(e: &Env, round_id: u32, committer: &Address) -> Option<Commitment> {
+    get_persistent(e, &Key::Commitment(round_id, committer.clone()))
+}
+
+/// Records that `committer` held `ticket_id` when committing in `round_id`.
+pub fn write_ticket_committer(e: &Env, round_id: u32, ticket_id: u32, committer: &Address) {
+    set_persistent(e, &Key::CommittedTicket(round_id, ticket_id), committer);
+}
+
+pub fn read_ticket_committer(e: &Env, round_id: u32, ticket_id: u32) -> Option<Address> {
+    get_persistent(e, &Key::CommittedTicket(round_id, ticket_id))
+}
+
+pub fn write_randomness(e: &Env, round_id: u32, randomness: &RoundRandomness) {
+    set_persistent(e, &Key::Randomness(round_id), randomness);
+}
+
+pub fn read_randomness(e: &Env, round_id: u32) -> RoundRandomness {
+    get_persistent(e, &Key::Randomness(round_id)).unwrap_or(RoundRandomness {
+        commitments: 0,
+        reveals: 0,
+        mix: BytesN::from_array(e, &[0; 32]),
+    })
+}
+
+pub fn write_prize_tiers(e: &Env, tiers: &Vec<PrizeTier>) {
+    set_persistent(e, &Key::PrizeTiers, tiers);
+}
+
+pub fn read_prize_tiers(e: &Env) -> Result<Vec<PrizeTier>, LotteryError> {
+    get_persistent(e, &Key::PrizeTiers).ok_or(LotteryError::PrizeTiersNotFound)
+}
+
+pub fn write_current_round(e: &Env, round: &RoundRecord) {
+    set_persistent(e, &Key::CurrentRound, round);
+}
+
+pub fn read_current_round(e: &Env) -> Result<RoundRecord, LotteryError> {
+    get_persistent(e, &Key::CurrentRound).ok_or(LotteryError::RoundNotFound)
+}
+
+pub fn write_round(e: &Env, round: &RoundRecord) {
+    set_persistent(e, &Key::Round(round.round_id), round);
+}
+
+pub fn read_round(e: &Env, round_id: u32) -> Result<RoundRecord, LotteryError> {
+    get_persistent(e, &Key::Round(round_id)).ok_or(LotteryError::RoundNotFound)
+}
+
+pub fn write_keeper_bounty(e: &Env, amount: &i128) {
+    set_instance(e, &Key::KeeperBounty, amount);
+}
+
+pub fn read_keeper_bounty(e: &Env) -> i128 {
+    get_instance(e, &Key::KeeperBounty).unwrap_or(0)
+}
+
+pub fn write_rolling_mode(e: &Env, enabled: bool) {
+    set_instance(e, &Key::RollingMode, &enabled);
+}
+
+pub fn read_rolling_mode(e: &Env) -> bool {
+    get_instance(e, &Key::RollingMode).unwrap_or(false)
+}
+
+pub fn write_phase_durations(e: &Env, durations: &PhaseDurations) {
+    set_instance(e, &Key::PhaseDurations, durations);
+}
+
+pub fn read_phase_durations(e: &Env) -> Result<PhaseDurations, LotteryError> {
+    get_instance(e, &Key::PhaseDurations).ok_or(LotteryError::PhaseDurationsNotFound)
+}
+
+pub fn write_pending_phase_durations(e: &Env, pending: &PendingPhaseDurations) {
+    set_instance(e, &Key::PendingPhaseDurations, pending);
+}
+
+pub fn read_pending_phase_durations(e: &Env) -> Option<PendingPhaseDurations> {
+    get_instance(e, &Key::PendingPhaseDurations)
+}
+
+pub fn remove_pending_phase_durations(e: &Env) {
+    e.storage().instance().remove(&Key::PendingPhaseDurations);
+}
+
+pub fn write_schema_version(e: &Env, version: u32) {
+    set_instance(e, &Key::SchemaVersion, &version);
+}
+
+pub fn read_schema_version(e: &Env) -> u32 {
+    get_instance(e, &Key::SchemaVersion).unwrap_or(1)
+}
+
+pub fn write_pending_upgrade(e: &Env, pending: &PendingUpgrade) {
+    set_instance(e, &Key::PendingUpgrade, pending);
+}
+
+pub fn read_pending_upgrade(e: &Env) -> Option<PendingUpgrade> {
+    get_instance(e, &Key::PendingUpgrade)
+}
+
+pub fn remove_pending_upgrade(e: &Env) {
+    e.storage().instance().remove(&Key::PendingUpgrade);
+}
+
+pub fn write_protocol_fee(e: &Env, fee: &ProtocolFee) {
+    set_instance(e, &Key::ProtocolFee, fee);
+}
+
+pub fn read_protocol_fee(e: &Env) -> Option<ProtocolFee> {
+    get_instance(e, &Key::ProtocolFee)
+}
+
+pub fn write_accrued_fees(e: &Env, token: &Address, amount: i128) {
+    set_persistent(e, &Key::AccruedFees(token.clone()), &amount);
+}
+
+pub fn read_accrued_fees(e: &Env, token: &Address) -> i128 {
+    get_persistent(e, &Key::AccruedFees(token.clone())).unwrap_or(0)
+}
+
+pub fn write_emissions_config(e: &Env, config: &EmissionsConfig) {
+    set_instance(e, &Key::EmissionsConfig, config);
+}
+
+pub fn read_emissions_config(e: &Env) -> Option<EmissionsConfig> {
+    get_instance(e, &Key::EmissionsConfig)
+}
+
+pub fn write_reserve_index(e: &Env, token: &Address, index: u32) {
+    set_persistent(e, &Key::ReserveIndex(token.clone()), &index);
+}
+
+pub fn read_reserve_index(e: &Env, token: &Address) -> Option<u32> {
+    get_persistent(e, &Key::ReserveIndex(token.clone()))
 }
@@ -1,11 +1,65 @@
 use crate::{
     error::LotteryError,
+    events,
     storage::{
-        self, LotteryStatus, MIN_BUYIN_TIME_IN_LEDGERS, MIN_ENDED_TIME_IN_LEDGERS,
-        MIN_YIELD_TIME_IN_LEDGERS,
+        self, AdvanceStep, LotteryStatus, PhaseDurations, Pool, PoolRecord, PrizeTier, Role,
+        RoundRecord, Ticket, BPS_DENOMINATOR, MAX_PRIZE_TIERS, SCALAR_12,
     },
 };
-use soroban_sdk::{Bytes, Env};
+use soroban_sdk::{Address, Bytes, BytesN, Env, Map, Vec};
+use stellar_non_fungible::{enumerable::Enumerable, Base};
+
+/// Requires auth from `caller` and that it holds `role`. The admin holds
+/// every role.
+pub fn require_role(e: &Env, caller: &Address, role: &Role) -> Result<(), LotteryError> {
+    caller.require_auth();
+
+    if !storage::is_admin(e, caller) && !storage::has_role(e, role, caller) {
+        return Err(LotteryError::NotAuthorized);
+    }
+    Ok(())
+}
+
+/// Ids of the ticket NFTs currently held by `owner`.
+pub fn owner_ticket_ids(e: &Env, owner: &Address) -> Vec<u32> {
+    let mut ticket_ids = Vec::new(e);
+    for index in 0..Base::balance(e, owner) {
+        ticket_ids.push_back(Enumerable::get_owner_token_id(e, owner, index));
+    }
+    ticket_ids
+}
+
+/// Moves a ticket to its new holder after an NFT transfer and keeps the
+/// participant count in step with the holders.
+pub fn track_ticket_transfer(
+    e: &Env,
+    from: &Address,
+    to: &Address,
+    ticket_id: u32,
+) -> Result<(), LotteryError> {
+    let mut ticket = storage::read_ticket(e, ticket_id)?;
+    ticket.user = to.clone();
+    storage::update_ticket(e, &ticket);
+
+    // A position stays with its ticket, the sender opens a new one on deposit
+    if storage::read_position(e, from, &ticket.token) == Some(ticket_id) {
+        storage::remove_position(e, from, &ticket.token);
+    }
+
+    if from == to {
+        return Ok(());
+    }
+
+    let mut state = storage::read_lottery_state(e)?;
+    if Base::balance(e, to) == 1 {
+        state.no_participants += 1;
+    }
+    if Base::balance(e, from) == 0 {
+        state.no_participants -= 1;
+    }
+    storage::write_lottery_state(e, &state);
+    Ok(())
+}
 
 pub fn generate_and_write_seed(e: &Env) {
     let timestamp = e.ledger().timestamp();
@@ -20,15 +74,68 @@ pub fn generate_and_write_seed(e: &Env) {
     storage::write_seed(e, &seed_bytes);
 }
 
+/// Mixes the revealed secrets and the host PRNG of the raffle call into the
+/// ledger seed. The PRNG is mixed in even without commitments, so whoever
+/// moves the lottery to `Ended` cannot pick the winner through the ledger
+/// they do it in, and a committer who withheld their reveal cannot rely on
+/// the outcome they could see before deciding.
+pub fn mix_reveals(e: &Env, seed: &Bytes) -> Result<Bytes, LotteryError> {
+    let round_id = storage::read_current_round(e)?.round_id;
+    let randomness = storage::read_randomness(e, round_id);
+
+    let mut seed_data = seed.clone();
+    seed_data.append(&randomness.mix.into());
+    let delayed: BytesN<32> = e.prng().gen();
+    seed_data.append(&delayed.into());
+
+    Ok(e.crypto().sha256(&seed_data).into())
+}
+
+/// Folds a revealed secret into the round's mix.
+pub fn xor_secret(e: &Env, mix: &BytesN<32>, secret: &BytesN<32>) -> BytesN<32> {
+    let mut mixed = mix.to_array();
+    for (byte, secret_byte) in mixed.iter_mut().zip(secret.to_array()) {
+        *byte ^= secret_byte;
+    }
+    BytesN::from_array(e, &mixed)
+}
+
+/// True when `ticket` belongs to a committer who never revealed in
+/// `round_id`, either its owner at commit time or its current owner. Moving
+/// the ticket after committing does not lift the forfeit.
+pub fn has_withheld_reveal(e: &Env, round_id: u32, ticket: &Ticket) -> bool {
+    let withheld = |committer: &Address| {
+        storage::read_commitment(e, round_id, committer)
+            .is_some_and(|commitment| !commitment.revealed)
+    };
+    storage::read_ticket_committer(e, round_id, ticket.id)
+        .is_some_and(|committer| withheld(&committer))
+        || withheld(&ticket.user)
+}
+
+/// True once every committer has revealed or the reveal window has passed.
+pub fn is_reveal_phase_over(e: &Env) -> Result<bool, LotteryError> {
+    let round_id = storage::read_current_round(e)?.round_id;
+    let randomness = storage::read_randomness(e, round_id);
+    if randomness.reveals == randomness.commitments {
+        return Ok(true);
+    }
+
+    let reveal_ledgers = storage::read_phase_durations(e)?.reveal_ledgers;
+    let ended_started_ledger = storage::read_ended_started_ledger(e)?;
+    Ok(e.ledger().sequence() >= ended_started_ledger + reveal_ledgers)
+}
+
 pub fn is_timelock_passed(
     e: &Env,
     current_status: &LotteryStatus,
     new_status: &LotteryStatus,
 ) -> Result<bool, LotteryError> {
+    let durations = storage::read_phase_durations(e)?;
     let required_ledgers = match (current_status, new_status) {
-        (LotteryStatus::BuyIn, LotteryStatus::YieldFarming) => MIN_BUYIN_TIME_IN_LEDGERS,
-        (LotteryStatus::YieldFarming, LotteryStatus::Ended) => MIN_YIELD_TIME_IN_LEDGERS,
-        (LotteryStatus::Ended, LotteryStatus::BuyIn) => MIN_ENDED_TIME_IN_LEDGERS,
+        (LotteryStatus::BuyIn, LotteryStatus::YieldFarming) => durations.buyin_ledgers,
+        (LotteryStatus::YieldFarming, LotteryStatus::Ended) => durations.farming_ledgers,
+        (LotteryStatus::Ended, LotteryStatus::BuyIn) => durations.ended_ledgers,
         _ => return Ok(false),
     };
 
@@ -41,3 +148,400 @@ pub fn is_timelock_passed(
     let current_ledger = e.ledger().sequence();
     Ok(current_ledger >= started_ledger + required_ledgers)
 }
+
+pub fn validate_prize_tiers(tiers: &Vec<PrizeTier>) -> Result<(), LotteryError> {
+    if tiers.is_empty() || tiers.len() > MAX_PRIZE_TIERS {
+        return Err(LotteryError::InvalidPrizeTiers);
+    }
+
+    let mut total_bps: u32 = 0;
+    for tier in tiers.iter() {
+        if tier.share_bps == 0 {
+            return Err(LotteryError::InvalidPrizeTiers);
+        }
+        total_bps = total_bps.saturating_add(tier.share_bps);
+    }
+
+    if total_bps != BPS_DENOMINATOR {
+        return Err(LotteryError::InvalidPrizeTiers);
+    }
+    Ok(())
+}
+
+/// Splits `prize` over the first `winners` tiers. Shares of tiers without a
+/// winner and the rounding remainder both go to the first tier.
+pub fn split_prize(e: &Env, prize: i128, tiers: &Vec<PrizeTier>, winners: u32) -> Vec<i128> {
+    let mut prizes = Vec::new(e);
+    let mut distributed: i128 = 0;
+    for tier in tiers.iter().take(winners as usize) {
+        let tier_prize = prize * tier.share_bps as i128 / BPS_DENOMINATOR as i128;
+        prizes.push_back(tier_prize);
+        distributed += tier_prize;
+    }
+
+    if let Some(first_prize) = prizes.first() {
+        prizes.set(0, first_prize + prize - distributed);
+    }
+    prizes
+}
+
+/// Underlying value of Blend b-tokens at `b_rate`, rounded down like Blend
+/// does when paying out a withdrawal.
+pub fn b_tokens_to_underlying(b_tokens: i128, b_rate: i128) -> i128 {
+    b_tokens * b_rate / SCALAR_12
+}
+
+/// What `blend_it` supplies for the pool: the principal and the prize pot.
+/// Unclaimed prizes and fees stay in the contract, so their yield is never
+/// drawn as a new prize.
+pub fn blend_amount(pool: &Pool) -> i128 {
+    pool.principal + pool.amount_of_yield + pool.jackpot
+}
+
+/// Everything the pool's token balance and Blend position have to cover.
+pub fn liabilities(e: &Env, pool: &Pool) -> i128 {
+    pool.principal
+        + pool.unclaimed_prizes
+        + pool.amount_of_yield
+        + pool.jackpot
+        + storage::read_accrued_fees(e, &pool.token)
+}
+
+/// The treasury's cut of `yield_gained`, rounded down. A loss is never
+/// charged.
+pub fn protocol_fee(e: &Env, yield_gained: i128) -> i128 {
+    match storage::read_protocol_fee(e) {
+        Some(fee) if yield_gained > 0 => {
+            yield_gained * fee.fee_bps as i128 / BPS_DENOMINATOR as i128
+        }
+        _ => 0,
+    }
+}
+
+pub fn new_round_record(e: &Env, round_id: u32) -> RoundRecord {
+    let mut pools = Vec::new(e);
+    for token in storage::read_pool_tokens(e).iter() {
+        pools.push_back(new_pool_record(&token));
+    }
+
+    RoundRecord {
+        round_id,
+        buyin_start_ledger: e.ledger().sequence(),
+        buyin_end_ledger: 0,
+        farming_start_ledger: 0,
+        farming_end_ledger: 0,
+        ended_start_ledger: 0,
+        ended_end_ledger: 0,
+        no_participants: 0,
+        no_tickets: 0,
+        pools,
+        emissions: 0,
+        winning_ticket_ids: Vec::new(e),
+        seed: Bytes::new(e),
+    }
+}
+
+pub fn new_pool_record(token: &Address) -> PoolRecord {
+    PoolRecord {
+        token: token.clone(),
+        total_deposited: 0,
+        amount_of_yield: 0,
+        prize_pot: 0,
+    }
+}
+
+/// Applies `update` to the current round's record of the pool for `token`.
+pub fn update_pool_record(
+    e: &Env,
+    token: &Address,
+    update: impl FnOnce(&mut PoolRecord),
+) -> Result<(), LotteryError> {
+    let mut round = storage::read_current_round(e)?;
+    let index = round
+        .pools
+        .iter()
+        .position(|record| record.token == *token)
+        .ok_or(LotteryError::PoolNotFound)? as u32;
+
+    let mut record = round.pools.get_unchecked(index);
+    update(&mut record);
+    round.pools.set(index, record);
+    storage::write_current_round(e, &round);
+    Ok(())
+}
+
+pub fn read_pools(e: &Env) -> Result<Vec<Pool>, LotteryError> {
+    let mut pools = Vec::new(e);
+    for token in storage::read_pool_tokens(e).iter() {
+        pools.push_back(storage::read_pool(e, &token)?);
+    }
+    Ok(pools)
+}
+
+/// Active tickets over every pool.
+pub fn active_ticket_total(e: &Env) -> u32 {
+    storage::read_pool_tokens(e)
+        .iter()
+        .map(|token| storage::read_active_ticket_count(e, &token))
+        .sum()
+}
+
+/// Records the ledger of a status change in the current round. Moving back to
+/// `BuyIn` archives the round and opens the next one.
+pub fn track_round_transition(e: &Env, new_status: &LotteryStatus) -> Result<(), LotteryError> {
+    let mut round = storage::read_current_round(e)?;
+    let current_ledger = e.ledger().sequence();
+
+    match new_status {
+        LotteryStatus::YieldFarming => {
+            round.buyin_end_ledger = current_ledger;
+            round.farming_start_ledger = current_ledger;
+        }
+        LotteryStatus::Ended => {
+            round.farming_end_ledger = current_ledger;
+            round.ended_start_ledger = current_ledger;
+        }
+        LotteryStatus::BuyIn => {
+            let state = storage::read_lottery_state(e)?;
+            round.ended_end_ledger = current_ledger;
+            round.no_participants = state.no_participants;
+            round.no_tickets = active_ticket_total(e);
+            storage::write_round(e, &round);
+
+            round = new_round_record(e, round.round_id + 1);
+        }
+    }
+
+    storage::write_current_round(e, &round);
+    Ok(())
+}
+
+pub fn validate_phase_durations(durations: &PhaseDurations) -> Result<(), LotteryError> {
+    if durations.buyin_ledgers == 0
+        || durations.farming_ledgers == 0
+        || durations.ended_ledgers == 0
+        || durations.reveal_ledgers > durations.ended_ledgers
+    {
+        return Err(LotteryError::InvalidPhaseDurations);
+    }
+    Ok(())
+}
+
+/// Applies proposed phase durations once their timelock has passed. Only
+/// called when a new round starts, so a round never changes its durations.
+pub fn apply_pending_phase_durations(e: &Env) {
+    if let Some(pending) = storage::read_pending_phase_durations(e) {
+        if e.ledger().sequence() >= pending.unlock_ledger {
+            storage::write_phase_durations(e, &pending.durations);
+            storage::remove_pending_phase_durations(e);
+            events::phase_durations_applied(e, &pending.durations);
+        }
+    }
+}
+
+/// Index of `token` in the Blend pool's `reserve_list`. The cached index is
+/// checked against the list on every use and resolved again if the reserve
+/// moved, so a position or claim never targets another reserve.
+pub fn reserve_index(
+    e: &Env,
+    reserve_list: &Vec<Address>,
+    token: &Address,
+) -> Result<u32, LotteryError> {
+    if let Some(index) = storage::read_reserve_index(e, token) {
+        if reserve_list.get(index).as_ref() == Some(token) {
+            return Ok(index);
+        }
+    }
+
+    let index = reserve_list
+        .first_index_of(token)
+        .ok_or(LotteryError::ReserveNotFound)?;
+    storage::write_reserve_index(e, token, index);
+    events::reserve_index_updated(e, token, index);
+    Ok(index)
+}
+
+/// Picks the lifecycle step that `advance` performs next.
+pub fn next_advance_step(e: &Env) -> Result<AdvanceStep, LotteryError> {
+    let state = storage::read_lottery_state(e)?;
+    let step = match state.status {
+        LotteryStatus::BuyIn => AdvanceStep::StartFarming,
+        LotteryStatus::YieldFarming => {
+            let has_funds = read_pools(e)?.iter().any(|pool| blend_amount(&pool) > 0);
+            if !state.in_blender && has_funds {
+                AdvanceStep::DepositToBlend
+            } else {
+                AdvanceStep::EndFarming
+            }
+        }
+        LotteryStatus::Ended => {
+            if state.in_blender {
+                AdvanceStep::WithdrawFromBlend
+            } else if is_raffle_due(e)? {
+                AdvanceStep::Raffle
+            } else {
+                AdvanceStep::StartBuyIn
+            }
+        }
+    };
+    Ok(step)
+}
+
+/// Ledger that time-weighted odds are measured up to: the end of farming
+/// while the round waits for its draw, the current ledger otherwise.
+pub fn odds_ledger(e: &Env) -> Result<u32, LotteryError> {
+    if is_awaiting_draw(e)? {
+        storage::read_ended_started_ledger(e)
+    } else {
+        Ok(e.ledger().sequence())
+    }
+}
+
+fn is_awaiting_draw(e: &Env) -> Result<bool, LotteryError> {
+    Ok(storage::read_lottery_status(e)? == LotteryStatus::Ended
+        && !storage::read_winner_selected(e)?)
+}
+
+/// Deposits are taken during `BuyIn`, or at any time in rolling mode except
+/// while the round waits for its draw, so the weights it draws against stay
+/// fixed.
+pub fn require_deposits_open(e: &Env) -> Result<(), LotteryError> {
+    if storage::read_lottery_status(e)? == LotteryStatus::BuyIn {
+        return Ok(());
+    }
+
+    if !storage::read_rolling_mode(e) || is_awaiting_draw(e)? {
+        return Err(LotteryError::WrongStatus);
+    }
+    Ok(())
+}
+
+/// Principal times ledgers held up to `ledger`, from the ticket's principal
+/// and its ledger-weighted principal.
+pub fn time_weight(principal: i128, entry: i128, ledger: u32) -> i128 {
+    principal * ledger as i128 - entry
+}
+
+/// Node of the pool's time-weight tree at `ledger`, with the raffle's
+/// `weight_deltas` applied on top of the stored trees.
+fn weight_node(
+    e: &Env,
+    token: &Address,
+    weight_deltas: &Map<u32, i128>,
+    ledger: u32,
+    node: u32,
+) -> i128 {
+    let weight = time_weight(
+        storage::read_ticket_weight_node(e, token, node),
+        storage::read_ticket_entry_node(e, token, node),
+        ledger,
+    );
+    weight + weight_deltas.get(node).unwrap_or(0)
+}
+
+/// Summed time weight of the first `no_tickets` active tickets of the pool
+/// at `ledger`.
+pub fn total_weight(
+    e: &Env,
+    token: &Address,
+    weight_deltas: &Map<u32, i128>,
+    ledger: u32,
+    no_tickets: u32,
+) -> i128 {
+    let mut sum = 0;
+    let mut node = no_tickets;
+    while node > 0 {
+        sum += weight_node(e, token, weight_deltas, ledger, node);
+        node &= node - 1;
+    }
+    sum
+}
+
+/// Index of the pool's active ticket whose cumulative weight range holds
+/// `target`, for `target` in `0..total_weight`. Tickets without weight are
+/// never picked.
+pub fn find_weighted_ticket(
+    e: &Env,
+    token: &Address,
+    weight_deltas: &Map<u32, i128>,
+    ledger: u32,
+    no_tickets: u32,
+    target: i128,
+) -> u32 {
+    let mut index = 0;
+    let mut remaining = target;
+    let mut step = 1 << (u32::BITS - 1 - no_tickets.leading_zeros());
+    while step > 0 {
+        let node = index + step;
+        if node <= no_tickets {
+            let weight = weight_node(e, token, weight_deltas, ledger, node);
+            if weight <= remaining {
+                index = node;
+                remaining -= weight;
+            }
+        }
+        step >>= 1;
+    }
+    index
+}
+
+/// Takes the ticket at `index` out of later draws by zeroing its weight in
+/// `weight_deltas`. Storage is left untouched.
+pub fn exclude_ticket_weight(
+    weight_deltas: &mut Map<u32, i128>,
+    no_tickets: u32,
+    index: u32,
+    weight: i128,
+) {
+    let mut node = index + 1;
+    while node <= no_tickets {
+        let delta = weight_deltas.get(node).unwrap_or(0);
+        weight_deltas.set(node, delta - weight);
+        node += node & node.wrapping_neg();
+    }
+}
+
+/// Draws a value in `0..total` from the seeded PRNG. Time weights outgrow
+/// `u64`, so two draws are combined; the modulo bias is below `total / 2^128`.
+pub fn draw_below(e: &Env, total: i128) -> i128 {
+    let prng = e.prng();
+    let high: u64 = prng.gen();
+    let low: u64 = prng.gen();
+    let value = ((high as u128) << 64) | low as u128;
+    (value % total as u128) as i128
+}
+
+/// A raffle is due once some pool with weighted tickets has a prize pot that
+/// reaches its minimum.
+fn is_raffle_due(e: &Env) -> Result<bool, LotteryError> {
+    if storage::read_winner_selected(e)? || !is_reveal_phase_over(e)? {
+        return Ok(false);
+    }
+
+    let odds_ledger = odds_ledger(e)?;
+    for pool in read_pools(e)?.iter() {
+        // Tickets bought in the ledger farming ended are never drawn
+        let no_tickets = storage::read_active_ticket_count(e, &pool.token);
+        if total_weight(e, &pool.token, &Map::new(e), odds_ledger, no_tickets) <= 0 {
+            continue;
+        }
+
+        if pool.amount_of_yield + pool.jackpot >= pool.min_prize {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// The keeper bounty payable from `prize_yield`, the yield a pool has just
+/// realized net of fees, capped by it. The jackpot is never touched.
+pub fn keeper_bounty(e: &Env, prize_yield: i128) -> i128 {
+    storage::read_keeper_bounty(e).min(prize_yield).max(0)
+}
+
+/// Ledgers in a full round with the current phase durations. Timelocked
+/// admin changes wait this long so users can exit first.
+pub fn round_length(e: &Env) -> Result<u32, LotteryError> {
+    let durations = storage::read_phase_durations(e)?;
+    Ok(durations.buyin_ledgers + durations.farming_ledgers + durations.ended_ledgers)
+}
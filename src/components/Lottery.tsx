@@ -15,6 +15,7 @@ import FundAccountButton from "./FundAccountButton";
 import packageJson from "../../package.json";
 import { getSorobanErrorMessage } from "../util/errorHandling";
 import { useStatusCountdown } from "../hooks/useStatusCountdown";
+import { DEFAULT_PHASE_DURATIONS, PhaseDurations } from "../util/constants";
 
 // 80s Amber Terminal Theme CSS
 const amberStyles = `
@@ -469,6 +470,9 @@ export const Lottery = () => {
   const [statusStartedLedger, setStatusStartedLedger] = useState<number | null>(
     null,
   );
+  const [phaseDurations, setPhaseDurations] = useState<PhaseDurations>(
+    DEFAULT_PHASE_DURATIONS,
+  );
 
   const { address, signTransaction } = useWallet();
   const { isFunded } = useWalletBalance();
@@ -479,6 +483,7 @@ export const Lottery = () => {
     lotteryState?.status ?? null,
     currentLedger,
     statusStartedLedger,
+    phaseDurations,
   );
 
   // Helper to update transaction status
@@ -505,6 +510,17 @@ export const Lottery = () => {
         const ledger = startedLedgerResult.result.unwrap();
         setStatusStartedLedger(Number(ledger));
       }
+
+      const durationsResult = await lotteryContract.get_phase_durations();
+      if (durationsResult.result.isOk()) {
+        const durations = durationsResult.result.unwrap();
+        setPhaseDurations({
+          buyin_ledgers: durations.buyin_ledgers,
+          farming_ledgers: durations.farming_ledgers,
+          ended_ledgers: durations.ended_ledgers,
+          reveal_ledgers: durations.reveal_ledgers,
+        });
+      }
     } catch (error) {
       console.error("Error loading ledger data:", error);
     }
@@ -679,8 +695,8 @@ export const Lottery = () => {
     setIsSubmitting(true);
     try {
       updateTxStatus("redeemTicket", "Preparing redemption...");
-      const tx = await lottery.redeem_ticket(
-        { ticket },
+      const tx = await lottery.redeem_ticket_by_id(
+        { user: ticket.user, ticket_id: ticket.id },
         { fee: 5000000 }, // 0.5 XLM in stroops
       );
 
@@ -737,7 +753,8 @@ export const Lottery = () => {
         addNotification(errorMessage, "error");
         setTimeout(clearTxStatus, 5000);
       } else {
-        const winner = response.result.unwrap();
+        const winners = response.result.unwrap();
+        const winner = winners[0];
         const winnerTicket: Ticket = {
           id: winner.id,
           user: winner.user,
@@ -750,7 +767,10 @@ export const Lottery = () => {
         updateTxStatus("raffle", "Updating lottery state...");
         await loadUserTickets();
         await loadLotteryState();
-        const successMsg = `Ticket #${winner.id} won the lottery!`;
+        const successMsg =
+          winners.length > 1
+            ? `Tickets ${winners.map((w) => `#${w.id}`).join(", ")} won the lottery!`
+            : `Ticket #${winner.id} won the lottery!`;
         updateTxStatus("raffle", successMsg, false);
         addNotification(successMsg, "success");
         setTimeout(clearTxStatus, 3000);
@@ -824,7 +844,10 @@ export const Lottery = () => {
     setIsSubmitting(true);
     try {
       updateTxStatus("blendIt", "Preparing to send funds to Blend...");
-      const tx = await lottery.blend_it({ fee: 5000000 }); // 0.5 XLM in stroops
+      const tx = await lottery.blend_it(
+        { caller: address },
+        { fee: 5000000 }, // 0.5 XLM in stroops
+      );
 
       updateTxStatus("blendIt", "Simulating transaction...");
 
@@ -870,7 +893,10 @@ export const Lottery = () => {
         "withdrawFromBlend",
         "Preparing to withdraw from Blend...",
       );
-      const tx = await lottery.withdraw_from_blend({ fee: 5000000 }); // 0.5 XLM in stroops
+      const tx = await lottery.withdraw_from_blend(
+        { caller: address },
+        { fee: 5000000 }, // 0.5 XLM in stroops
+      );
 
       updateTxStatus("withdrawFromBlend", "Simulating transaction...");
 
@@ -1,5 +1,6 @@
 import { useState, useEffect, useCallback } from "react";
 import {
+  PhaseDurations,
   SECONDS_PER_LEDGER,
   getMinimumLedgersForStatus,
   ledgersToTimeString,
@@ -20,6 +21,7 @@ export function useStatusCountdown(
   currentStatus: "BuyIn" | "YieldFarming" | "Ended" | null,
   currentLedger: number | null,
   statusStartedLedger: number | null,
+  phaseDurations: PhaseDurations,
 ): CountdownData {
   const [localCurrentLedger, setLocalCurrentLedger] = useState<number | null>(
     currentLedger,
@@ -55,7 +57,10 @@ export function useStatusCountdown(
       };
     }
 
-    const totalRequired = getMinimumLedgersForStatus(currentStatus);
+    const totalRequired = getMinimumLedgersForStatus(
+      currentStatus,
+      phaseDurations,
+    );
     const elapsed = localCurrentLedger - statusStartedLedger;
     const remaining = Math.max(0, totalRequired - elapsed);
     const canTransition = remaining === 0;
@@ -66,7 +71,12 @@ export function useStatusCountdown(
       canTransition,
       totalRequired,
     };
-  }, [currentStatus, localCurrentLedger, statusStartedLedger]);
+  }, [
+    currentStatus,
+    localCurrentLedger,
+    statusStartedLedger,
+    phaseDurations,
+  ]);
 
   return calculateCountdown();
 }
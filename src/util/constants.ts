@@ -1,6 +1,6 @@
 /**
  * Ledger and timelock constants matching the smart contract
- * These values must match those in contracts/no-loss-lottery/src/storage.rs
+ * Phase durations are configured per deployment and read from the contract
  */
 
 // Average time per ledger in seconds
@@ -9,24 +9,38 @@ export const SECONDS_PER_LEDGER = 6;
 // Ledger calculations
 const DAY_IN_LEDGERS = 17300;
 
-// Minimum time in ledgers required for each status transition
-export const MIN_BUYIN_TIME_IN_LEDGERS = DAY_IN_LEDGERS; // 1 day
-export const MIN_YIELD_TIME_IN_LEDGERS = DAY_IN_LEDGERS * 6; // 6 days
-export const MIN_ENDED_TIME_IN_LEDGERS = DAY_IN_LEDGERS; // 1 day
+/**
+ * Phase durations in ledgers, as returned by `get_phase_durations`
+ */
+export interface PhaseDurations {
+  buyin_ledgers: number;
+  farming_ledgers: number;
+  ended_ledgers: number;
+  reveal_ledgers: number;
+}
+
+// Used until the deployed durations have been loaded from the contract
+export const DEFAULT_PHASE_DURATIONS: PhaseDurations = {
+  buyin_ledgers: DAY_IN_LEDGERS, // 1 day
+  farming_ledgers: DAY_IN_LEDGERS * 6, // 6 days
+  ended_ledgers: DAY_IN_LEDGERS, // 1 day
+  reveal_ledgers: DAY_IN_LEDGERS / 2, // 12 hours
+};
 
 /**
  * Get the minimum required ledgers for a status transition
  */
 export function getMinimumLedgersForStatus(
   status: "BuyIn" | "YieldFarming" | "Ended",
+  durations: PhaseDurations,
 ): number {
   switch (status) {
     case "BuyIn":
-      return MIN_BUYIN_TIME_IN_LEDGERS;
+      return durations.buyin_ledgers;
     case "YieldFarming":
-      return MIN_YIELD_TIME_IN_LEDGERS;
+      return durations.farming_ledgers;
     case "Ended":
-      return MIN_ENDED_TIME_IN_LEDGERS;
+      return durations.ended_ledgers;
   }
 }
 